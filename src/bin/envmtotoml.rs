@@ -0,0 +1,513 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use envmtotoml::{
+    ArrayMerge, ConflictPolicy, Converter, Dotenv, Env, EnvFormat, KeyCase, KeyOrder,
+    ListDelimiter, Redaction, Schema, TableMerge, TableStyle, VarSource,
+};
+
+const USAGE: &str = "\
+Usage: envmtotoml [OPTIONS]
+
+Converts prefixed environment variables into a TOML document.
+
+Options:
+  -p, --prefix <PREFIX>      Only convert variables starting with PREFIX
+      --prefix-table <PREFIX>=<TABLE>
+                             Also convert variables starting with PREFIX, placing
+                             them under the root TABLE; repeatable
+      --ignore-prefix-case   Match prefixes regardless of case
+      --keep-prefix          Keep the prefix as a root table, e.g. APP_PORT as
+                             app.port
+  -s, --separator <SEP>      Separator between table and key segments, such as
+                             __, _, . or : [default: __]
+  -e, --env-file <PATH>      Read variables from a .env file instead of the
+                             environment; repeat to layer files in order
+  -o, --output <PATH>        Atomically write the TOML to PATH instead of stdout
+      --template <PATH>      Coerce values to the types of the same keys in the
+                             TOML file at PATH instead of inferring them
+      --schema <PATH>        Validate against the schema at PATH, a JSON Schema
+                             if it ends in .json and a TOML schema otherwise
+      --check                Only validate the variables, writing no output
+      --defaults <PATH>      Fill keys no variable sets from the TOML file at PATH
+      --redact <MODE>        Mask secret values in the output: placeholder or
+                             fingerprint
+      --secret <PATTERN>     Also mask the values of variables matching PATTERN;
+                             requires --redact
+      --annotate-sources     Comment each value with the variable it came from,
+                             or with `default`
+      --merge <PATH>         Apply the variables on top of the TOML file at PATH,
+                             preserving its comments and formatting
+      --table-merge <MODE>   How --merge merges tables: deep or replace
+                             [default: deep]
+      --array-merge <MODE>   How --merge merges arrays: replace or append
+                             [default: replace]
+      --from-toml <PATH>     Convert the TOML file at PATH into variables instead,
+                             inverting the naming options
+      --format <FORMAT>      Output format of --from-toml: dotenv, shell or json
+                             [default: dotenv]
+      --key-case <CASE>      Casing of tables and keys: preserve, lower, snake,
+                             kebab, camel or pascal [default: lower]
+      --rename <SEGMENT>=<KEY>
+                             Use KEY for every SEGMENT of a variable name;
+                             repeatable
+      --no-infer             Emit every value as a string
+      --no-arrays            Keep numeric segments such as APP_HOSTS__0 as table keys
+      --string <VAR>         Always emit the variable VAR as a string; repeatable
+      --list <PATTERN>[=<DELIM>]
+                             Split variables matching PATTERN (`*` is a wildcard)
+                             into arrays on DELIM: comma, semicolon, whitespace or
+                             a single character; repeatable [default: comma]
+      --list-suffix <SUFFIX>[=<DELIM>]
+                             Split variables ending with SUFFIX into arrays and
+                             drop the suffix from the key [default: comma]
+      --json <PATTERN>       Parse variables matching PATTERN as JSON; repeatable
+      --detect-json          Parse values that start with `{` or `[` as JSON when
+                             they are valid JSON
+      --file-suffix <SUFFIX> Read variables ending with SUFFIX, such as _FILE, as
+                             paths to files holding the value
+      --interpolate          Expand ${VAR}, ${VAR:-default} and ${VAR:?error}
+                             references in values; $$ is a literal $
+      --conflicts <POLICY>   Resolve keys also used as tables: error, table or
+                             reserved=<KEY> [default: error]
+      --order <ORDER>        Order of tables and keys: alphabetical, discovery or
+                             priority=<PATH>,... [default: alphabetical]
+      --table-style <DEPTH>=<STYLE>
+                             Write tables at DEPTH (1 = top level) as header,
+                             dotted or inline tables; repeatable [default: header]
+  -h, --help                 Print this help
+  -V, --version              Print the version
+";
+
+/// What the command line asked for.
+#[derive(Debug)]
+enum Command {
+    Help,
+    Version,
+    Convert(Box<Args>),
+}
+
+/// Options for a conversion run.
+#[derive(Debug, Default)]
+struct Args {
+    converter: Converter,
+    env_files: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    template: Option<PathBuf>,
+    schema: Option<PathBuf>,
+    check: bool,
+    defaults: Option<PathBuf>,
+    redact: bool,
+    merge: Option<PathBuf>,
+    from_toml: Option<PathBuf>,
+    format: Option<EnvFormat>,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("envmtotoml {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Convert(args)) => *args,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Parses command-line arguments, accepting both `--flag value` and `--flag=value`.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut parsed = Args::default();
+    let mut secrets = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("`{}` requires a value", flag))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-p" | "--prefix" => parsed.converter = parsed.converter.prefix(value()?),
+            "--prefix-table" => {
+                let value = value()?;
+                let (prefix, table) = value
+                    .split_once('=')
+                    .filter(|(prefix, table)| !prefix.is_empty() && !table.is_empty())
+                    .ok_or_else(|| format!("invalid prefix table `{}`", value))?;
+                parsed.converter = parsed.converter.prefix_table(prefix, table)
+            }
+            "--ignore-prefix-case" => parsed.converter = parsed.converter.ignore_prefix_case(true),
+            "--keep-prefix" => parsed.converter = parsed.converter.keep_prefix(true),
+            "-s" | "--separator" => parsed.converter = parsed.converter.separator(value()?),
+            "-e" | "--env-file" => parsed.env_files.push(value()?.into()),
+            "-o" | "--output" => parsed.output = Some(value()?.into()),
+            "--template" => parsed.template = Some(value()?.into()),
+            "--schema" => parsed.schema = Some(value()?.into()),
+            "--check" => parsed.check = true,
+            "--defaults" => parsed.defaults = Some(value()?.into()),
+            "--redact" => {
+                let redaction = match value()?.as_str() {
+                    "placeholder" => Redaction::default(),
+                    "fingerprint" => Redaction::Fingerprint,
+                    other => return Err(format!("invalid redaction `{}`", other)),
+                };
+                parsed.converter = parsed.converter.redaction(redaction);
+                parsed.redact = true
+            }
+            "--secret" => {
+                parsed.converter = parsed.converter.secret(value()?);
+                secrets = true
+            }
+            "--file-suffix" => parsed.converter = parsed.converter.file_suffix(value()?),
+            "--interpolate" => parsed.converter = parsed.converter.interpolate(true),
+            "--annotate-sources" => parsed.converter = parsed.converter.annotate_sources(true),
+            "--merge" => parsed.merge = Some(value()?.into()),
+            "--table-merge" => {
+                let merge = match value()?.as_str() {
+                    "deep" => TableMerge::Deep,
+                    "replace" => TableMerge::Replace,
+                    other => return Err(format!("invalid table merge `{}`", other)),
+                };
+                parsed.converter = parsed.converter.table_merge(merge)
+            }
+            "--array-merge" => {
+                let merge = match value()?.as_str() {
+                    "replace" => ArrayMerge::Replace,
+                    "append" => ArrayMerge::Append,
+                    other => return Err(format!("invalid array merge `{}`", other)),
+                };
+                parsed.converter = parsed.converter.array_merge(merge)
+            }
+            "--from-toml" => parsed.from_toml = Some(value()?.into()),
+            "--format" => parsed.format = Some(parse_format(&value()?)?),
+            "--key-case" => {
+                parsed.converter = parsed.converter.key_case(parse_key_case(&value()?)?)
+            }
+            "--rename" => {
+                let value = value()?;
+                let (segment, key) = value
+                    .split_once('=')
+                    .filter(|(segment, key)| !segment.is_empty() && !key.is_empty())
+                    .ok_or_else(|| format!("invalid rename `{}`", value))?;
+                parsed.converter = parsed.converter.rename(segment, key)
+            }
+            "--no-infer" => parsed.converter = parsed.converter.infer_types(false),
+            "--no-arrays" => parsed.converter = parsed.converter.indexed_arrays(false),
+            "--string" => parsed.converter = parsed.converter.force_string(value()?),
+            "--list" => {
+                let (pattern, delimiter) = parse_list(&value()?)?;
+                parsed.converter = parsed.converter.list(pattern, delimiter)
+            }
+            "--list-suffix" => {
+                let (suffix, delimiter) = parse_list(&value()?)?;
+                parsed.converter = parsed.converter.list_suffix(suffix, delimiter)
+            }
+            "--json" => parsed.converter = parsed.converter.json(value()?),
+            "--detect-json" => parsed.converter = parsed.converter.detect_json(true),
+            "--conflicts" => {
+                parsed.converter = parsed
+                    .converter
+                    .conflict_policy(parse_conflicts(&value()?)?)
+            }
+            "--order" => parsed.converter = parsed.converter.key_order(parse_order(&value()?)?),
+            "--table-style" => {
+                let (depth, style) = parse_table_style(&value()?)?;
+                parsed.converter = parsed.converter.table_style(depth, style)
+            }
+            _ => return Err(format!("unexpected argument `{}`", flag)),
+        }
+    }
+    if secrets && !parsed.redact {
+        return Err("`--secret` requires `--redact`".to_string());
+    }
+    Ok(Command::Convert(Box::new(parsed)))
+}
+
+fn parse_conflicts(value: &str) -> Result<ConflictPolicy, String> {
+    match value {
+        "error" => Ok(ConflictPolicy::Error),
+        "table" => Ok(ConflictPolicy::TableWins),
+        _ => match value.strip_prefix("reserved=") {
+            Some(key) if !key.is_empty() => Ok(ConflictPolicy::ReservedKey(key.to_string())),
+            _ => Err(format!("invalid conflict policy `{}`", value)),
+        },
+    }
+}
+
+fn parse_list(value: &str) -> Result<(String, ListDelimiter), String> {
+    let (name, delimiter) = match value.split_once('=') {
+        Some((name, delimiter)) => (name, delimiter),
+        None => (value, "comma"),
+    };
+    let delimiter = match delimiter {
+        "comma" => ListDelimiter::Comma,
+        "semicolon" => ListDelimiter::Semicolon,
+        "whitespace" => ListDelimiter::Whitespace,
+        _ => {
+            let mut chars = delimiter.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => ListDelimiter::Char(c),
+                _ => return Err(format!("invalid list delimiter `{}`", delimiter)),
+            }
+        }
+    };
+    if name.is_empty() {
+        return Err(format!("invalid list `{}`", value));
+    }
+    Ok((name.to_string(), delimiter))
+}
+
+fn parse_format(value: &str) -> Result<EnvFormat, String> {
+    match value {
+        "dotenv" => Ok(EnvFormat::Dotenv),
+        "shell" => Ok(EnvFormat::Shell),
+        "json" => Ok(EnvFormat::Json),
+        _ => Err(format!("invalid format `{}`", value)),
+    }
+}
+
+fn parse_key_case(value: &str) -> Result<KeyCase, String> {
+    match value {
+        "preserve" => Ok(KeyCase::Preserve),
+        "lower" => Ok(KeyCase::Lower),
+        "snake" => Ok(KeyCase::Snake),
+        "kebab" => Ok(KeyCase::Kebab),
+        "camel" => Ok(KeyCase::Camel),
+        "pascal" => Ok(KeyCase::Pascal),
+        _ => Err(format!("invalid key case `{}`", value)),
+    }
+}
+
+fn parse_order(value: &str) -> Result<KeyOrder, String> {
+    match value {
+        "alphabetical" => Ok(KeyOrder::Alphabetical),
+        "discovery" => Ok(KeyOrder::Discovery),
+        _ => match value.strip_prefix("priority=") {
+            Some(paths) => Ok(KeyOrder::Priority(
+                paths.split(',').map(str::to_string).collect(),
+            )),
+            None => Err(format!("invalid order `{}`", value)),
+        },
+    }
+}
+
+fn parse_table_style(value: &str) -> Result<(usize, TableStyle), String> {
+    let invalid = || format!("invalid table style `{}`", value);
+    let (depth, style) = value.split_once('=').ok_or_else(invalid)?;
+    let depth = depth.parse().map_err(|_| invalid())?;
+    let style = match style {
+        "header" => TableStyle::Header,
+        "dotted" => TableStyle::Dotted,
+        "inline" => TableStyle::Inline,
+        _ => return Err(invalid()),
+    };
+    Ok((depth, style))
+}
+
+/// Converts the selected variables, or the TOML file, and writes the result.
+fn run(args: &Args) -> Result<(), String> {
+    let output = match &args.from_toml {
+        Some(path) => {
+            let toml = read(path)?;
+            let vars = args
+                .converter
+                .to_env(&toml)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            args.format.unwrap_or(EnvFormat::Dotenv).format(&vars)
+        }
+        None => convert(args)?,
+    };
+    if args.check {
+        return Ok(());
+    }
+
+    match &args.output {
+        Some(path) => write_atomically(path, &output)
+            .map_err(|err| format!("could not write `{}`: {}", path.display(), err)),
+        None => io::stdout()
+            .write_all(output.as_bytes())
+            .map_err(|err| format!("could not write to stdout: {}", err)),
+    }
+}
+
+/// Converts the selected variables into TOML, merging them into the `--merge`
+/// document if there is one.
+fn convert(args: &Args) -> Result<String, String> {
+    let dotenv = args
+        .env_files
+        .iter()
+        .fold(Dotenv::new(), |dotenv, path| dotenv.file(path));
+    let source: &dyn VarSource = if args.env_files.is_empty() {
+        &Env
+    } else {
+        &dotenv
+    };
+    let mut converter = args.converter.clone();
+    if let Some(path) = &args.template {
+        converter = converter.template(read(path)?);
+    }
+    if let Some(path) = &args.defaults {
+        converter = converter.defaults(read(path)?);
+    }
+    if let Some(path) = &args.schema {
+        let contents = read(path)?;
+        let schema = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            Schema::from_json_schema(&contents)
+        } else {
+            Schema::from_toml(&contents)
+        }
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+        converter = converter.schema(schema);
+    }
+    match &args.merge {
+        Some(path) => converter.merge_source(&read(path)?, source),
+        None => converter.convert_source(source),
+    }
+    .and_then(|toml| match args.redact {
+        true => converter.redact(&toml),
+        false => Ok(toml),
+    })
+    .map_err(|err| err.to_string())
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| format!("could not read `{}`: {}", path.display(), err))
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it into place,
+/// so readers never observe a partially written file.
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let Ok(Command::Convert(args)) = parse(&[
+            "--prefix=APP_",
+            "-e",
+            ".env",
+            "--env-file",
+            ".env.local",
+            "-o",
+            "config.toml",
+            "--conflicts",
+            "reserved=_value",
+            "--order=priority=server,db",
+        ]) else {
+            panic!("expected a conversion");
+        };
+        assert_eq!(
+            args.env_files,
+            [PathBuf::from(".env"), PathBuf::from(".env.local")]
+        );
+        assert_eq!(args.output, Some(PathBuf::from("config.toml")));
+
+        let toml = args
+            .converter
+            .convert_vars([
+                ("APP_DB", "x"),
+                ("APP_DB__HOST", "y"),
+                ("APP_SERVER__PORT", "1"),
+            ])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "\n[server]\nport = 1\n\n[db]\n_value = \"x\"\nhost = \"y\"\n"
+        );
+
+        assert!(matches!(parse(&["-h"]), Ok(Command::Help)));
+        assert_eq!(
+            parse(&["--prefix"]).unwrap_err(),
+            "`--prefix` requires a value"
+        );
+        assert!(parse(&["--order", "random"]).is_err());
+        assert!(parse(&["--redact", "hide"]).is_err());
+        assert_eq!(
+            parse(&["--secret", "*token*"]).unwrap_err(),
+            "`--secret` requires `--redact`"
+        );
+        assert!(parse(&["--secret", "*token*", "--redact", "placeholder"]).is_ok());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["--prefix-table", "APP_"]).is_err());
+        assert_eq!(parse_key_case("kebab"), Ok(KeyCase::Kebab));
+        assert!(parse_key_case("upper").is_err());
+        assert_eq!(parse_format("shell"), Ok(EnvFormat::Shell));
+        assert!(parse_format("yaml").is_err());
+        assert!(parse(&["--table-merge", "shallow"]).is_err());
+        assert_eq!(parse_table_style("2=inline"), Ok((2, TableStyle::Inline)));
+        assert!(parse_table_style("inline").is_err());
+        assert_eq!(
+            parse_list("APP_*"),
+            Ok(("APP_*".to_string(), ListDelimiter::Comma))
+        );
+        assert_eq!(
+            parse_list("APP_X=|"),
+            Ok(("APP_X".to_string(), ListDelimiter::Char('|')))
+        );
+        assert!(parse_list("APP_X=tab").is_err());
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        let path = env::temp_dir().join(format!("envmtotoml-cli-{}.toml", std::process::id()));
+        write_atomically(&path, "a = 1\n").unwrap();
+        write_atomically(&path, "a = 2\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = 2\n");
+        fs::remove_file(&path).unwrap();
+    }
+}
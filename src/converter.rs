@@ -0,0 +1,821 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::de::DeserializeOwned;
+
+use crate::de;
+use crate::json;
+use crate::merge;
+use crate::pattern::wildcard_match;
+use crate::redact;
+use crate::reverse;
+use crate::{Config, Env, Error, Redaction, Schema, VarSource};
+
+/// Builder for converting prefixed environment variables into a TOML document.
+///
+/// ```no_run
+/// use envmtotoml::Converter;
+///
+/// let toml = Converter::new()
+///     .prefix("APP_")
+///     .force_string("APP_VERSION")
+///     .convert()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Converter {
+    pub(crate) prefix: String,
+    pub(crate) prefix_tables: Vec<(String, String)>,
+    pub(crate) ignore_prefix_case: bool,
+    pub(crate) keep_prefix: bool,
+    pub(crate) separator: String,
+    pub(crate) key_case: KeyCase,
+    pub(crate) renames: HashMap<String, String>,
+    pub(crate) infer_types: bool,
+    pub(crate) string_vars: HashSet<String>,
+    pub(crate) conflict_policy: ConflictPolicy,
+    pub(crate) key_order: KeyOrder,
+    pub(crate) table_styles: HashMap<usize, TableStyle>,
+    pub(crate) indexed_arrays: bool,
+    pub(crate) lists: Vec<(String, ListDelimiter)>,
+    pub(crate) list_suffix: Option<(String, ListDelimiter)>,
+    pub(crate) json_vars: Vec<String>,
+    pub(crate) detect_json: bool,
+    pub(crate) interpolate: bool,
+    pub(crate) file_suffix: Option<String>,
+    pub(crate) file_size_limit: u64,
+    pub(crate) table_merge: TableMerge,
+    pub(crate) array_merge: ArrayMerge,
+    pub(crate) template: Option<String>,
+    pub(crate) schema: Option<Schema>,
+    pub(crate) defaults: Option<String>,
+    pub(crate) default_values: Vec<(String, String)>,
+    pub(crate) annotate_sources: bool,
+    pub(crate) redaction: Redaction,
+    pub(crate) secret_keys: Vec<String>,
+    pub(crate) secret_vars: Vec<String>,
+}
+
+/// Order in which tables and keys are written to the generated document.
+///
+/// Top-level keys always come before tables, as TOML requires.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum KeyOrder {
+    /// Sort tables and keys alphabetically by their path.
+    #[default]
+    Alphabetical,
+    /// Keep the order in which variables were read from their source.
+    ///
+    /// For the process environment this is the platform's order, which is not stable.
+    Discovery,
+    /// Emit the listed dotted paths (tables such as `db` or keys such as `db.host`)
+    /// first, in list order, followed by everything else alphabetically.
+    Priority(Vec<String>),
+}
+
+impl KeyOrder {
+    /// Sorts `entries` given each entry's path segments and discovery position.
+    pub(crate) fn sort<T>(
+        &self,
+        entries: &mut [T],
+        path: impl Fn(&T) -> Vec<String>,
+        position: impl Fn(&T) -> usize,
+    ) {
+        match self {
+            KeyOrder::Alphabetical => entries.sort_by_cached_key(path),
+            KeyOrder::Discovery => entries.sort_by_key(position),
+            KeyOrder::Priority(priorities) => entries.sort_by_cached_key(|entry| {
+                let path = path(entry);
+                let dotted = path.join(".");
+                let rank = priorities
+                    .iter()
+                    .position(|priority| *priority == dotted)
+                    .unwrap_or(usize::MAX);
+                (rank, path)
+            }),
+        }
+    }
+}
+
+/// Delimiter used to split a single variable into a TOML array.
+///
+/// A backslash escapes the delimiter (or another backslash) so it is kept inside an
+/// element. Elements are trimmed, empty elements are dropped, and each element's
+/// type is inferred like any other value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListDelimiter {
+    Comma,
+    Semicolon,
+    /// Any run of whitespace.
+    Whitespace,
+    Char(char),
+}
+
+impl ListDelimiter {
+    fn matches(self, c: char) -> bool {
+        match self {
+            ListDelimiter::Comma => c == ',',
+            ListDelimiter::Semicolon => c == ';',
+            ListDelimiter::Whitespace => c.is_whitespace(),
+            ListDelimiter::Char(delimiter) => c == delimiter,
+        }
+    }
+
+    /// Splits `raw` into trimmed, non-empty elements.
+    pub(crate) fn split(self, raw: &str) -> Vec<String> {
+        let mut elements = Vec::new();
+        let mut current = String::new();
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some(next) if next == '\\' || self.matches(next) => current.push(next),
+                    Some(next) => {
+                        current.push(c);
+                        current.push(next);
+                    }
+                    None => current.push(c),
+                }
+            } else if self.matches(c) {
+                elements.push(std::mem::take(&mut current));
+            } else {
+                current.push(c);
+            }
+        }
+        elements.push(current);
+        elements
+            .into_iter()
+            .map(|element| element.trim().to_string())
+            .filter(|element| !element.is_empty())
+            .collect()
+    }
+
+    /// Joins `elements` with the delimiter, escaping delimiters and backslashes
+    /// within them so that [`ListDelimiter::split`] gives them back.
+    pub(crate) fn join(self, elements: &[String]) -> String {
+        let separator = match self {
+            ListDelimiter::Comma => ',',
+            ListDelimiter::Semicolon => ';',
+            ListDelimiter::Whitespace => ' ',
+            ListDelimiter::Char(delimiter) => delimiter,
+        };
+        let mut joined = String::new();
+        for (i, element) in elements.iter().enumerate() {
+            if i > 0 {
+                joined.push(separator);
+            }
+            for c in element.chars() {
+                if c == '\\' || self.matches(c) {
+                    joined.push('\\');
+                }
+                joined.push(c);
+            }
+        }
+        joined
+    }
+}
+
+/// Casing applied to each table and key segment of a variable name.
+///
+/// Except for [`KeyCase::Preserve`] and [`KeyCase::Lower`], segments are split into
+/// words at `_`, `-` and case changes, so `MAX_CONNECTIONS` and `maxConnections`
+/// both become `max-connections` in kebab case.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyCase {
+    /// Keeps the segment as written in the variable name.
+    Preserve,
+    /// Lowercases the segment, keeping any underscores.
+    #[default]
+    Lower,
+    /// `max_connections`
+    Snake,
+    /// `max-connections`
+    Kebab,
+    /// `maxConnections`
+    Camel,
+    /// `MaxConnections`
+    Pascal,
+}
+
+impl KeyCase {
+    /// Converts a single segment of a variable name.
+    pub(crate) fn apply(self, segment: &str) -> String {
+        let capitalize = |word: &str| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        };
+        match self {
+            KeyCase::Preserve => segment.to_string(),
+            KeyCase::Lower => segment.to_lowercase(),
+            KeyCase::Snake => words(segment).join("_").to_lowercase(),
+            KeyCase::Kebab => words(segment).join("-").to_lowercase(),
+            KeyCase::Camel => words(segment)
+                .iter()
+                .enumerate()
+                .map(|(i, word)| {
+                    if i == 0 {
+                        word.to_lowercase()
+                    } else {
+                        capitalize(word)
+                    }
+                })
+                .collect(),
+            KeyCase::Pascal => words(segment).iter().map(|word| capitalize(word)).collect(),
+        }
+    }
+
+    /// Converts a key back into an uppercase variable name segment.
+    pub(crate) fn to_env(self, key: &str) -> String {
+        match self {
+            KeyCase::Preserve => key.to_string(),
+            KeyCase::Lower => key.to_uppercase(),
+            _ => words(key).join("_").to_uppercase(),
+        }
+    }
+}
+
+/// Splits `segment` into words at `_`, `-`, whitespace and case changes, keeping
+/// acronyms together, so `HTTPServer_port` gives `HTTP`, `Server` and `port`.
+fn words(segment: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for part in segment.split(|c: char| c == '_' || c == '-' || c.is_whitespace()) {
+        let chars: Vec<(usize, char)> = part.char_indices().collect();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (index, c) = chars[i];
+            let previous = chars[i - 1].1;
+            let next_is_lower = chars
+                .get(i + 1)
+                .is_some_and(|(_, next)| next.is_lowercase());
+            let boundary = c.is_uppercase()
+                && (previous.is_lowercase()
+                    || previous.is_ascii_digit()
+                    || (previous.is_uppercase() && next_is_lower));
+            if boundary {
+                words.push(&part[start..index]);
+                start = index;
+            }
+        }
+        words.push(&part[start..]);
+    }
+    words.retain(|word| !word.is_empty());
+    words
+}
+
+/// How a table is written within the generated document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TableStyle {
+    /// A `[a.b]` header followed by the table's keys.
+    #[default]
+    Header,
+    /// Dotted keys within the parent, such as `b.c = 1`.
+    Dotted,
+    /// An inline table within the parent, such as `b = { c = 1 }`.
+    Inline,
+}
+
+/// How a table from the environment is merged into an existing TOML document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TableMerge {
+    /// Merges the keys recursively, keeping keys the environment does not set.
+    #[default]
+    Deep,
+    /// Replaces the document's table with the one from the environment.
+    Replace,
+}
+
+/// How an array from the environment is merged into an existing TOML document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// Replaces the document's array. Under [`TableMerge::Deep`], the elements of
+    /// an array of tables are merged by index instead, keeping the keys and
+    /// elements the environment does not set.
+    #[default]
+    Replace,
+    /// Appends the elements to the document's array.
+    Append,
+}
+
+/// How to handle a variable whose key is also used as a table by another variable,
+/// such as `APP_DB=x` alongside `APP_DB__HOST=y`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fail with [`Error::KeyConflict`] naming both variables.
+    #[default]
+    Error,
+    /// Drop the scalar value and keep the table.
+    TableWins,
+    /// Move the scalar value into the table under the given key, e.g. `db._value`.
+    ReservedKey(String),
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            prefix_tables: Vec::new(),
+            ignore_prefix_case: false,
+            keep_prefix: false,
+            separator: "__".to_string(),
+            key_case: KeyCase::default(),
+            renames: HashMap::new(),
+            infer_types: true,
+            string_vars: HashSet::new(),
+            conflict_policy: ConflictPolicy::default(),
+            key_order: KeyOrder::default(),
+            table_styles: HashMap::new(),
+            indexed_arrays: true,
+            lists: Vec::new(),
+            list_suffix: None,
+            json_vars: Vec::new(),
+            detect_json: false,
+            interpolate: false,
+            file_suffix: None,
+            file_size_limit: 1024 * 1024,
+            table_merge: TableMerge::default(),
+            array_merge: ArrayMerge::default(),
+            template: None,
+            schema: None,
+            defaults: None,
+            default_values: Vec::new(),
+            annotate_sources: false,
+            redaction: Redaction::default(),
+            secret_keys: ["*password*", "*secret*", "*token*", "*_key"]
+                .map(String::from)
+                .to_vec(),
+            secret_vars: Vec::new(),
+        }
+    }
+}
+
+impl Converter {
+    /// Creates a converter with no prefix, a `__` separator and type inference enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the prefix that environment variables must start with to be converted.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Also converts variables starting with `prefix`, placing them under the root
+    /// table `table`, e.g. `APP_DB_` mapped to `database`. A dotted `table` nests
+    /// the variables further. Can be called repeatedly for several prefixes.
+    ///
+    /// When prefix tables are configured without [`Converter::prefix`], only
+    /// variables matching one of them are converted. A variable matching several
+    /// prefixes uses the longest one.
+    pub fn prefix_table(mut self, prefix: impl Into<String>, table: impl Into<String>) -> Self {
+        self.prefix_tables.push((prefix.into(), table.into()));
+        self
+    }
+
+    /// Sets whether prefixes match regardless of ASCII case, so `APP_` also matches
+    /// `app_port`. Disabled by default.
+    pub fn ignore_prefix_case(mut self, ignore: bool) -> Self {
+        self.ignore_prefix_case = ignore;
+        self
+    }
+
+    /// Sets whether the prefix set with [`Converter::prefix`] is kept as a root
+    /// table, named after the prefix without trailing separator characters and in
+    /// lowercase, so `APP_PORT` becomes `app.port`. Disabled by default.
+    pub fn keep_prefix(mut self, keep: bool) -> Self {
+        self.keep_prefix = keep;
+        self
+    }
+
+    /// Finds the longest prefix matching `var`, returning the root table path for
+    /// its variables and the rest of the name.
+    pub(crate) fn match_prefix<'a>(&self, var: &'a str) -> Option<(Vec<String>, &'a str)> {
+        let plain = (!self.prefix.is_empty() || self.prefix_tables.is_empty())
+            .then_some((self.prefix.as_str(), None));
+        let tables = self
+            .prefix_tables
+            .iter()
+            .map(|(prefix, table)| (prefix.as_str(), Some(table.as_str())));
+        let (prefix, table) = plain
+            .into_iter()
+            .chain(tables)
+            .filter(|(prefix, _)| match var.get(..prefix.len()) {
+                Some(head) if self.ignore_prefix_case => head.eq_ignore_ascii_case(prefix),
+                Some(head) => head == *prefix,
+                None => false,
+            })
+            .max_by_key(|(prefix, _)| prefix.len())?;
+
+        let root = match table {
+            Some(table) => table.split('.').map(str::to_string).collect(),
+            None if self.keep_prefix => {
+                let name = prefix.trim_end_matches(|c: char| !c.is_alphanumeric());
+                if name.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![self.convert_segment(name)]
+                }
+            }
+            None => Vec::new(),
+        };
+        Some((root, &var[prefix.len()..]))
+    }
+
+    /// Sets the separator between table and key segments of a variable name, such as
+    /// `__`, `_`, `.` or the `:` used by ASP.NET-style configuration.
+    ///
+    /// An empty separator disables nesting, so every variable becomes a top-level key.
+    pub fn separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Sets the casing of table and key segments. Defaults to [`KeyCase::Lower`].
+    pub fn key_case(mut self, case: KeyCase) -> Self {
+        self.key_case = case;
+        self
+    }
+
+    /// Renames every table or key segment spelled `segment` in variable names,
+    /// ignoring ASCII case, to `key`, which is used as is instead of applying the
+    /// key casing. For example `.rename("DB", "database")` turns `APP_DB__HOST`
+    /// into `database.host`.
+    pub fn rename(mut self, segment: impl AsRef<str>, key: impl Into<String>) -> Self {
+        self.renames
+            .insert(segment.as_ref().to_ascii_lowercase(), key.into());
+        self
+    }
+
+    /// Converts a segment of a variable name into a key, applying renames and casing.
+    pub(crate) fn convert_segment(&self, segment: &str) -> String {
+        match self.renames.get(&segment.to_ascii_lowercase()) {
+            Some(key) => key.clone(),
+            None => self.key_case.apply(segment),
+        }
+    }
+
+    /// Converts a key back into a segment of a variable name, reversing renames and
+    /// casing.
+    pub(crate) fn env_segment(&self, key: &str) -> String {
+        match self.renames.iter().find(|(_, renamed)| *renamed == key) {
+            Some((segment, _)) => segment.to_uppercase(),
+            None => self.key_case.to_env(key),
+        }
+    }
+
+    /// Enables or disables inference of integers, floats, booleans and datetimes.
+    ///
+    /// When disabled, every value is emitted as a TOML string.
+    pub fn infer_types(mut self, enabled: bool) -> Self {
+        self.infer_types = enabled;
+        self
+    }
+
+    /// Always emits the named environment variable (including its prefix) as a string.
+    pub fn force_string(mut self, var: impl Into<String>) -> Self {
+        self.string_vars.insert(var.into());
+        self
+    }
+
+    /// Sets how keys that are also used as tables are resolved.
+    pub fn conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.conflict_policy = policy;
+        self
+    }
+
+    /// Sets the order of tables and keys in the generated document.
+    pub fn key_order(mut self, order: KeyOrder) -> Self {
+        self.key_order = order;
+        self
+    }
+
+    /// Enables or disables building arrays from numeric segments.
+    ///
+    /// When enabled, `APP_HOSTS__0=a` and `APP_HOSTS__1=b` become `hosts = ["a", "b"]`,
+    /// and `APP_UPSTREAM__0__HOST` becomes an `[[upstream]]` array of tables.
+    /// Indices must start at zero, have no gaps and not be mixed with other keys.
+    /// When disabled, they become a `[hosts]` table with keys `0` and `1`.
+    pub fn indexed_arrays(mut self, enabled: bool) -> Self {
+        self.indexed_arrays = enabled;
+        self
+    }
+
+    /// Splits variables whose name matches `pattern` into arrays on `delimiter`.
+    ///
+    /// The pattern is a full variable name, including the prefix, where `*` matches
+    /// any run of characters, e.g. `APP_*_ORIGINS`. Matching ignores ASCII case.
+    pub fn list(mut self, pattern: impl Into<String>, delimiter: ListDelimiter) -> Self {
+        self.lists.push((pattern.into(), delimiter));
+        self
+    }
+
+    /// Splits variables whose name ends with `suffix` into arrays on `delimiter`,
+    /// removing the suffix from the key, so `APP_HOSTS__LIST=a,b` becomes
+    /// `hosts = ["a", "b"]` with a `__LIST` suffix. The suffix matches ignoring
+    /// ASCII case, like [`Converter::list`] patterns.
+    pub fn list_suffix(mut self, suffix: impl Into<String>, delimiter: ListDelimiter) -> Self {
+        self.list_suffix = Some((suffix.into(), delimiter));
+        self
+    }
+
+    /// Returns the delimiter of the first list pattern matching `var`.
+    pub(crate) fn list_delimiter_for(&self, var: &str) -> Option<ListDelimiter> {
+        self.lists
+            .iter()
+            .find(|(pattern, _)| wildcard_match(pattern, var))
+            .map(|(_, delimiter)| *delimiter)
+    }
+
+    /// Parses variables whose name matches `pattern` as JSON, merging objects,
+    /// arrays and scalars into the document as native TOML values.
+    ///
+    /// The pattern is matched like in [`Converter::list`]. Values that are not
+    /// valid JSON, or that contain `null`, are reported as errors.
+    pub fn json(mut self, pattern: impl Into<String>) -> Self {
+        self.json_vars.push(pattern.into());
+        self
+    }
+
+    /// Sets whether values that start with `{` or `[` and parse as JSON are
+    /// converted like the variables selected with [`Converter::json`]. Values that
+    /// do not parse are kept as plain values. Disabled by default.
+    pub fn detect_json(mut self, detect: bool) -> Self {
+        self.detect_json = detect;
+        self
+    }
+
+    /// Sets whether `${VAR}` references in the values of selected variables are
+    /// expanded before conversion, resolving them against the same source.
+    /// Disabled by default.
+    ///
+    /// `${VAR:-default}` uses `default` when `VAR` is unset or empty,
+    /// `${VAR:?message}` fails with `message` instead, and `$$` is a literal `$`.
+    /// Unset variables and reference cycles are reported as errors.
+    pub fn interpolate(mut self, enabled: bool) -> Self {
+        self.interpolate = enabled;
+        self
+    }
+
+    /// Reads variables ending with `suffix`, such as `APP_DB__PASSWORD_FILE` for
+    /// the suffix `_FILE`, as the path of a file whose trimmed contents are the
+    /// value of the key without the suffix. Disabled by default.
+    ///
+    /// This suits secrets mounted as files by Docker or Kubernetes. Setting both
+    /// `APP_DB__PASSWORD` and `APP_DB__PASSWORD_FILE` is an error, as are files
+    /// that cannot be read or exceed [`Converter::file_size_limit`].
+    pub fn file_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.file_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Sets the largest file, in bytes, that [`Converter::file_suffix`] variables
+    /// may name. Defaults to 1 MiB.
+    pub fn file_size_limit(mut self, bytes: u64) -> Self {
+        self.file_size_limit = bytes;
+        self
+    }
+
+    /// Whether the value of `var` should be parsed as JSON, and whether it must be.
+    pub(crate) fn json_mode(&self, var: &str, raw: &str) -> Option<bool> {
+        if self
+            .json_vars
+            .iter()
+            .any(|pattern| wildcard_match(pattern, var))
+        {
+            Some(true)
+        } else if self.detect_json && json::looks_like_json(raw) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Sets how tables at `depth` are written, top-level tables being at depth 1.
+    ///
+    /// Subtables of a dotted or inline table are always written in the same style
+    /// as part of it. Depths without an explicit style use [`TableStyle::Header`].
+    pub fn table_style(mut self, depth: usize, style: TableStyle) -> Self {
+        self.table_styles.insert(depth, style);
+        self
+    }
+
+    /// Returns the style of tables at `depth`.
+    pub(crate) fn table_style_at(&self, depth: usize) -> TableStyle {
+        self.table_styles.get(&depth).copied().unwrap_or_default()
+    }
+
+    /// Coerces each variable to the type of the same key in the TOML `template`,
+    /// such as a defaults file, instead of inferring it.
+    ///
+    /// Booleans also accept `1`/`0`, `yes`/`no` and `on`/`off`, integers are
+    /// accepted for floats, and values for arrays are split like list variables,
+    /// on commas by default. Values that do not fit fail with
+    /// [`Error::TypeMismatch`]. Keys missing from the template are inferred as
+    /// usual. The template is parsed when converting.
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    /// Fills keys that no variable sets with the values of the TOML document
+    /// `defaults`. Tables are filled key by key, while arrays set by a variable
+    /// replace the default array. The document is parsed when converting.
+    pub fn defaults(mut self, defaults: impl Into<String>) -> Self {
+        self.defaults = Some(defaults.into());
+        self
+    }
+
+    /// Uses `value` for the key at the dotted `path`, such as `db.pool_size`, when
+    /// no variable sets it. The value is converted as if a variable held it, and
+    /// takes precedence over the [`Converter::defaults`] document. A later call for
+    /// the same path replaces the earlier value.
+    pub fn default_value(mut self, path: impl Into<String>, value: impl Into<String>) -> Self {
+        let path = path.into();
+        self.default_values
+            .retain(|(existing, _)| *existing != path);
+        self.default_values.push((path, value.into()));
+        self
+    }
+
+    /// Sets whether each value in the output is followed by a comment noting its
+    /// source, such as `# from APP_PORT` or `# default`. Disabled by default.
+    pub fn annotate_sources(mut self, annotate: bool) -> Self {
+        self.annotate_sources = annotate;
+        self
+    }
+
+    /// Validates the converted configuration against `schema`, inserting its
+    /// defaults for keys that are not set.
+    ///
+    /// Keys with a type in the schema are coerced to it like with
+    /// [`Converter::template`]. Conversion fails with [`Error::Validation`]
+    /// holding every violation at once.
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Sets how tables are merged by [`Converter::merge`]. Defaults to [`TableMerge::Deep`].
+    pub fn table_merge(mut self, merge: TableMerge) -> Self {
+        self.table_merge = merge;
+        self
+    }
+
+    /// Sets how arrays are merged by [`Converter::merge`]. Defaults to [`ArrayMerge::Replace`].
+    pub fn array_merge(mut self, merge: ArrayMerge) -> Self {
+        self.array_merge = merge;
+        self
+    }
+
+    /// Flattens a TOML document into environment variables, inverting the naming
+    /// rules of this converter: prefixes, separator, key casing, renames, list and
+    /// JSON variables, and array indices.
+    ///
+    /// Converting the resulting variables with the same converter gives back the
+    /// document, except for strings that look like other types and empty tables.
+    /// Keys that would give invalid or ambiguous variable names, such as `max-conn`,
+    /// `a__b` with the default separator, `Port` (read back as `port`) or two keys
+    /// giving the same name, are reported as [`Error::InvalidKey`]; with
+    /// [`KeyCase::Kebab`], `max-conn` becomes `MAX_CONN`.
+    pub fn to_env(&self, toml: &str) -> Result<Vec<(String, String)>, Error> {
+        reverse::to_env(self, toml)
+    }
+
+    /// Sets how [`Converter::redact`] masks secret values. Defaults to the
+    /// `[REDACTED]` placeholder.
+    pub fn redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    /// Replaces the patterns for keys whose values are secret, by default
+    /// `*password*`, `*secret*`, `*token*` and `*_key`.
+    ///
+    /// Patterns are matched case-insensitively against each key and its variable
+    /// segment, so `*_key` matches both `api_key` and `apiKey`. Everything within
+    /// a secret table or array is secret too.
+    pub fn secret_keys<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.secret_keys = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Marks the values of variables whose name matches `pattern` as secret,
+    /// whatever their key. The pattern is matched like in [`Converter::list`].
+    pub fn secret(mut self, pattern: impl Into<String>) -> Self {
+        self.secret_vars.push(pattern.into());
+        self
+    }
+
+    /// Masks the secret values of a TOML document, such as one returned by
+    /// [`Converter::convert`], so that it can be logged. The document stays valid
+    /// TOML, with its comments and formatting kept; masked values become strings.
+    pub fn redact(&self, toml: &str) -> Result<String, Error> {
+        redact::redact(self, toml)
+    }
+
+    /// Converts the matching variables of the process environment into a TOML string.
+    pub fn convert(&self) -> Result<String, Error> {
+        self.convert_source(&Env)
+    }
+
+    /// Converts the matching `(name, value)` pairs into a TOML string.
+    pub fn convert_vars<I, K, V>(&self, vars: I) -> Result<String, Error>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let vars: Vec<(String, String)> = vars
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        self.convert_source(&vars)
+    }
+
+    /// Converts the matching variables of `source` into a TOML string.
+    pub fn convert_source<S: VarSource + ?Sized>(&self, source: &S) -> Result<String, Error> {
+        let config = Config::from_source(self, source)?;
+        Ok(config.to_toml(self))
+    }
+
+    /// Deserializes the matching variables of the process environment into `T`, see
+    /// [`Converter::deserialize_source`].
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        self.deserialize_source(&Env)
+    }
+
+    /// Deserializes the matching `(name, value)` pairs into `T`.
+    pub fn deserialize_vars<T, I, K, V>(&self, vars: I) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let vars: Vec<(String, String)> = vars
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        self.deserialize_source(&vars)
+    }
+
+    /// Deserializes the matching variables of `source` into `T` through the TOML data
+    /// model, without formatting and re-parsing a document. String fields get the
+    /// variable's text as written, so `APP_VERSION=1.10` gives `"1.10"` and
+    /// `APP_ZIP=01234` gives `"01234"`.
+    ///
+    /// Deserialization errors are reported as [`Error::Deserialize`], naming the
+    /// variable at the failing key rather than a position in a document.
+    pub fn deserialize_source<T, S>(&self, source: &S) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+        S: VarSource + ?Sized,
+    {
+        let config = Config::from_source(self, source)?;
+        de::deserialize(self, &config.root)
+    }
+
+    /// Applies the matching variables of the process environment on top of the TOML
+    /// `document`, see [`Converter::merge_source`].
+    pub fn merge(&self, document: &str) -> Result<String, Error> {
+        self.merge_source(document, &Env)
+    }
+
+    /// Applies the matching `(name, value)` pairs on top of the TOML `document`.
+    pub fn merge_vars<I, K, V>(&self, document: &str, vars: I) -> Result<String, Error>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let vars: Vec<(String, String)> = vars
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        self.merge_source(document, &vars)
+    }
+
+    /// Applies the matching variables of `source` on top of the TOML `document`.
+    ///
+    /// Variables take precedence over the document. Tables and arrays are merged as
+    /// set with [`Converter::table_merge`] and [`Converter::array_merge`], and keys
+    /// the variables do not touch keep their comments, order and formatting. New
+    /// tables are added at the end of the document.
+    pub fn merge_source<S: VarSource + ?Sized>(
+        &self,
+        document: &str,
+        source: &S,
+    ) -> Result<String, Error> {
+        let config = Config::from_source(self, source)?;
+        merge::merge(self, document, &config.root)
+    }
+}
@@ -0,0 +1,285 @@
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_path_to_error::Segment;
+
+use crate::table::{Node, Table};
+use crate::{reverse, Converter, Error};
+
+/// Deserializes the configuration tree `root` into `T`, reporting errors against
+/// the variable at the failing key.
+pub(crate) fn deserialize<T: DeserializeOwned>(
+    converter: &Converter,
+    root: &Table,
+) -> Result<T, Error> {
+    let node = Node::Table(root.clone());
+    serde_path_to_error::deserialize(NodeDeserializer(&node)).map_err(|err| {
+        let mut path: Vec<String> = err
+            .path()
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Seq { index } => Some(index.to_string()),
+                Segment::Map { key } => Some(key.clone()),
+                Segment::Enum { variant } => Some(variant.clone()),
+                Segment::Unknown => None,
+            })
+            .collect();
+        let reason = err.into_inner().message().to_string();
+
+        // A missing field has no variable of its own, so name the one that would set it.
+        let missing = reason
+            .strip_prefix("missing field `")
+            .and_then(|field| field.strip_suffix('`'));
+        let var = match missing {
+            Some(field) => {
+                path.push(field.to_string());
+                Some(reverse::var_name(converter, &path))
+            }
+            None => match root.get(&path).and_then(|node| node.first_var()) {
+                Some(var) => Some(var.to_string()),
+                None if !path.is_empty() => Some(reverse::var_name(converter, &path)),
+                None => None,
+            },
+        };
+        Error::Deserialize { var, reason }
+    })
+}
+
+/// Deserializes a node of the configuration tree. String targets get the text a
+/// value was read from, so `APP_VERSION=1.10` gives `"1.10"` rather than failing
+/// as a float; other values follow the `toml` crate's data model.
+struct NodeDeserializer<'a>(&'a Node);
+
+impl<'de> de::Deserializer<'de> for NodeDeserializer<'_> {
+    type Error = toml::de::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Node::Leaf(_) => de::Deserializer::deserialize_any(self.0.to_toml_value(), visitor),
+            Node::Table(table) => visitor.visit_map(TableAccess {
+                entries: table.entries().iter(),
+                value: None,
+            }),
+            Node::Array(elements) => visitor.visit_seq(ArrayAccess(elements.iter())),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Node::Leaf(leaf) => match &leaf.raw {
+                Some(raw) => visitor.visit_str(raw),
+                None => self.deserialize_any(visitor),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            // `toml::Value` hands datetimes out as strings, so re-read them the way a
+            // document would be for `toml::value::Datetime` targets.
+            Node::Leaf(_) => match self.0.to_toml_value() {
+                toml::Value::Datetime(datetime) => {
+                    toml::de::ValueDeserializer::new(&datetime.to_string())
+                        .deserialize_struct(name, fields, visitor)
+                }
+                value => value.deserialize_struct(name, fields, visitor),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0
+            .to_toml_value()
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map identifier ignored_any
+    }
+}
+
+struct TableAccess<'a> {
+    entries: std::slice::Iter<'a, (String, Node)>,
+    value: Option<&'a Node>,
+}
+
+impl<'de> de::MapAccess<'de> for TableAccess<'_> {
+    type Error = toml::de::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some((key, node)) = self.entries.next() else {
+            return Ok(None);
+        };
+        self.value = Some(node);
+        seed.deserialize(key.as_str().into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let node = self.value.take().expect("a value follows its key");
+        seed.deserialize(NodeDeserializer(node))
+    }
+}
+
+struct ArrayAccess<'a>(std::slice::Iter<'a, Node>);
+
+impl<'de> de::SeqAccess<'de> for ArrayAccess<'_> {
+    type Error = toml::de::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.0
+            .next()
+            .map(|node| seed.deserialize(NodeDeserializer(node)))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::{Converter, Error};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        name: String,
+        db: Database,
+        #[serde(default)]
+        hosts: Vec<String>,
+        #[serde(default)]
+        ports: Vec<u16>,
+        version: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Database {
+        port: u16,
+        timeout: Option<f64>,
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let converter = Converter::new().prefix("APP_");
+        let config: Config = converter
+            .deserialize_vars([
+                ("APP_NAME", "svc"),
+                ("APP_DB__PORT", "5432"),
+                ("APP_HOSTS__0", "a"),
+            ])
+            .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                name: "svc".to_string(),
+                db: Database {
+                    port: 5432,
+                    timeout: None,
+                },
+                hosts: vec!["a".to_string()],
+                ports: Vec::new(),
+                version: None,
+            }
+        );
+
+        let err = converter
+            .deserialize_vars::<Config, _, _, _>([("APP_NAME", "svc"), ("APP_DB__PORT", "x")])
+            .unwrap_err();
+        assert_eq!(err.var(), Some("APP_DB__PORT"));
+        assert!(matches!(err, Error::Deserialize { .. }));
+
+        let err = converter
+            .deserialize_vars::<Config, _, _, _>([("APP_NAME", "svc"), ("APP_DB__TIMEOUT", "1")])
+            .unwrap_err();
+        assert_eq!(err.var(), Some("APP_DB__PORT"));
+        assert_eq!(
+            err.to_string(),
+            "environment variable `APP_DB__PORT` could not be deserialized: missing field `port`"
+        );
+
+        // Strings are deserialized from the variable's text, whatever type it looks like.
+        let config: Config = converter
+            .deserialize_vars([
+                ("APP_NAME", "12345"),
+                ("APP_VERSION", "1.10"),
+                ("APP_DB__PORT", "1"),
+                ("APP_HOSTS__0", "1"),
+                ("APP_HOSTS__1", "true"),
+                ("APP_PORTS__0", "80"),
+            ])
+            .unwrap();
+        assert_eq!(config.name, "12345");
+        assert_eq!(config.version.as_deref(), Some("1.10"));
+        assert_eq!(config.hosts, ["1", "true"]);
+        assert_eq!(config.ports, [80]);
+
+        let err = converter
+            .deserialize_vars::<Config, _, _, _>([
+                ("APP_NAME", "svc"),
+                ("APP_DB__PORT", "1"),
+                ("APP_PORTS__0", "80"),
+                ("APP_PORTS__1", "x"),
+            ])
+            .unwrap_err();
+        assert_eq!(err.var(), Some("APP_PORTS__1"));
+    }
+
+    #[test]
+    fn test_deserialize_datetime() {
+        #[derive(Debug, Deserialize)]
+        struct Schedule {
+            start: toml::value::Datetime,
+            end: Option<toml::value::Datetime>,
+        }
+
+        let converter = Converter::new().prefix("APP_");
+        let schedule: Schedule = converter
+            .deserialize_vars([
+                ("APP_START", "1979-05-27"),
+                ("APP_END", "1979-05-27T07:32:00Z"),
+            ])
+            .unwrap();
+        assert_eq!(schedule.start.to_string(), "1979-05-27");
+        assert_eq!(schedule.end.unwrap().to_string(), "1979-05-27T07:32:00Z");
+
+        let err = converter
+            .deserialize_vars::<Schedule, _, _, _>([("APP_START", "soon")])
+            .unwrap_err();
+        assert_eq!(err.var(), Some("APP_START"));
+    }
+}
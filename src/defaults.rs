@@ -0,0 +1,112 @@
+use crate::table::{Leaf, Node, Table};
+use crate::template::{self, Template};
+use crate::value::Value;
+use crate::{reverse, ConflictPolicy, Converter, Error};
+
+/// Fills keys missing from `root` with the converter's default values, first those
+/// set with [`Converter::default_value`], then those of the defaults document.
+pub(crate) fn apply(
+    converter: &Converter,
+    root: &mut Table,
+    template: Option<&Template>,
+) -> Result<(), Error> {
+    let mut defaults = Table::default();
+    let mut keys: Vec<(String, &str)> = Vec::new();
+    for (key, raw) in &converter.default_values {
+        let mut path: Vec<String> = key.split('.').map(str::to_string).collect();
+        let var = reverse::var_name(converter, &path);
+        keys.push((var.clone(), key));
+        let expected = converter
+            .schema
+            .as_ref()
+            .and_then(|schema| schema.expected(&path))
+            .or_else(|| template.and_then(|template| template.get(&path)).cloned());
+        let mut node = match expected {
+            Some(expected) => template::coerce(&expected, raw, &var, usize::MAX, None)?,
+            None => Node::Leaf(Leaf {
+                value: if converter.infer_types {
+                    Value::infer(raw)
+                } else {
+                    Value::String(raw.clone())
+                },
+                var,
+                position: usize::MAX,
+                default: false,
+                raw: Some(raw.clone()),
+            }),
+        };
+        node.mark_default();
+        let leaf_key = path.pop().unwrap_or_default();
+        defaults
+            .insert(&path, leaf_key, node, &ConflictPolicy::Error)
+            .map_err(|err| match err {
+                // No variable is involved, so name the conflicting defaults instead.
+                Error::KeyConflict { var, table_var } => {
+                    let key_of = |var: &str| {
+                        keys.iter()
+                            .find(|(default_var, _)| *default_var == var)
+                            .map_or_else(|| var.to_string(), |(_, key)| key.to_string())
+                    };
+                    Error::DefaultConflict {
+                        key: key_of(&var),
+                        table_key: key_of(&table_var),
+                    }
+                }
+                err => err,
+            })?;
+    }
+    root.fill(defaults);
+
+    if let Some(document) = &converter.defaults {
+        let document: toml::Table =
+            document
+                .parse()
+                .map_err(|err: toml::de::Error| Error::InvalidToml {
+                    reason: format!("defaults: {}", err.message()),
+                })?;
+        let Node::Table(defaults) =
+            to_node(converter, &toml::Value::Table(document), &mut Vec::new())
+        else {
+            unreachable!("a document converts to a table");
+        };
+        root.fill(defaults);
+    }
+    Ok(())
+}
+
+/// Converts a value of the defaults document into a node marked as default, naming
+/// the variable that would override it.
+fn to_node(converter: &Converter, value: &toml::Value, path: &mut Vec<String>) -> Node {
+    let value = match value {
+        toml::Value::Table(table) => {
+            let mut node = Table::default();
+            for (key, value) in table {
+                path.push(key.clone());
+                node.push(key.clone(), to_node(converter, value, path));
+                path.pop();
+            }
+            return Node::Table(node);
+        }
+        toml::Value::Array(elements) => {
+            let mut nodes = Vec::with_capacity(elements.len());
+            for (index, element) in elements.iter().enumerate() {
+                path.push(index.to_string());
+                nodes.push(to_node(converter, element, path));
+                path.pop();
+            }
+            return Node::Array(nodes);
+        }
+        toml::Value::String(value) => Value::String(value.clone()),
+        toml::Value::Integer(value) => Value::Integer(*value),
+        toml::Value::Float(value) => Value::Float(*value),
+        toml::Value::Boolean(value) => Value::Boolean(*value),
+        toml::Value::Datetime(value) => Value::Datetime(value.to_string()),
+    };
+    Node::Leaf(Leaf {
+        value,
+        var: reverse::var_name(converter, path),
+        position: usize::MAX,
+        default: true,
+        raw: None,
+    })
+}
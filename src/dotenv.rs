@@ -0,0 +1,238 @@
+//! `.env` loading. The parser is this crate's own rather than dotenvy's, because
+//! dotenvy substitutes `${VAR}` references from the process environment, while a
+//! [`Dotenv`] source never reads or writes `std::env`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{Error, VarSource};
+
+/// Variables read from one or more `.env` files or strings, without loading them
+/// into the process environment.
+///
+/// Inputs are layered in the order they are added: a variable defined again by a
+/// later input (or later in the same input) overrides the earlier value but keeps
+/// its original position. `$` references in values are kept verbatim, see
+/// [`Converter::interpolate`](crate::Converter::interpolate) to expand them.
+///
+/// ```no_run
+/// use envmtotoml::{Converter, Dotenv};
+///
+/// let dotenv = Dotenv::new().file(".env").file(".env.local");
+/// let toml = Converter::new().prefix("APP_").convert_source(&dotenv).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Dotenv {
+    inputs: Vec<Input>,
+}
+
+#[derive(Debug, Clone)]
+enum Input {
+    File(PathBuf),
+    Contents(String),
+}
+
+impl Dotenv {
+    /// Creates an empty source.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a `.env` file, read when the variables are requested.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.inputs.push(Input::File(path.into()));
+        self
+    }
+
+    /// Adds the contents of a `.env` file.
+    pub fn contents(mut self, contents: impl Into<String>) -> Self {
+        self.inputs.push(Input::Contents(contents.into()));
+        self
+    }
+
+    /// Reads and parses every input, applying later definitions over earlier ones.
+    fn load(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut vars: Vec<(String, String)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for input in &self.inputs {
+            let (path, contents) = match input {
+                Input::File(path) => {
+                    let contents = fs::read_to_string(path).map_err(|err| Error::Io {
+                        path: path.clone(),
+                        reason: err.to_string(),
+                    })?;
+                    (Some(path), contents)
+                }
+                Input::Contents(contents) => (None, contents.clone()),
+            };
+
+            let entries = parse(&contents).map_err(|(line, reason)| Error::Dotenv {
+                path: path.cloned(),
+                line: Some(line),
+                reason,
+            })?;
+            for (key, value) in entries {
+                match positions.get(&key) {
+                    Some(&position) => vars[position].1 = value,
+                    None => {
+                        positions.insert(key.clone(), vars.len());
+                        vars.push((key, value));
+                    }
+                }
+            }
+        }
+        Ok(vars)
+    }
+}
+
+impl VarSource for Dotenv {
+    fn vars(&self) -> Box<dyn Iterator<Item = Result<(String, String), Error>> + '_> {
+        match self.load() {
+            Ok(vars) => Box::new(vars.into_iter().map(Ok)),
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        }
+    }
+}
+
+/// Parses `.env` contents into variables, or returns the line and reason of the
+/// first error.
+///
+/// Values are single-quoted (literal), double-quoted (with `\` escapes) or
+/// unquoted, may be concatenated as in `'a'"b"c`, and may be followed by a
+/// `# comment`. Unlike shells, `$` references are kept verbatim, so the result
+/// never depends on the process environment.
+fn parse(contents: &str) -> Result<Vec<(String, String)>, (usize, String)> {
+    let mut vars = Vec::new();
+    for (number, line) in LogicalLines::new(contents) {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let unparsable = || (number, format!("could not parse `{}`", trimmed.trim_end()));
+        let statement = trimmed
+            .strip_prefix("export")
+            .filter(|rest| rest.starts_with([' ', '\t']))
+            .map_or(trimmed, str::trim_start);
+        let key_len = statement
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(statement.len());
+        let (key, rest) = statement.split_at(key_len);
+        let Some(raw) = rest.trim_start_matches([' ', '\t']).strip_prefix('=') else {
+            return Err(unparsable());
+        };
+        if key.is_empty() {
+            return Err(unparsable());
+        }
+        let value = parse_value(raw).map_err(|reason| {
+            (
+                number,
+                format!("could not parse the value of `{}`: {}", key, reason),
+            )
+        })?;
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
+/// Parses the value after `=`, which may span several lines if it is quoted.
+fn parse_value(raw: &str) -> Result<String, String> {
+    let mut value = String::new();
+    let mut chars = raw.trim_start_matches([' ', '\t']).chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some(c) => value.push(c),
+                    None => return Err("missing closing `'`".to_string()),
+                }
+            },
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('r') => value.push('\r'),
+                        Some('t') => value.push('\t'),
+                        Some(c @ ('\\' | '"' | '\'' | '$')) => value.push(c),
+                        Some(c) => return Err(format!("invalid escape `\\{}`", c)),
+                        None => return Err("missing closing `\"`".to_string()),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err("missing closing `\"`".to_string()),
+                }
+            },
+            '\\' => match chars.next() {
+                Some(c) => value.push(c),
+                None => return Err("trailing `\\`".to_string()),
+            },
+            ' ' | '\t' | '\r' | '\n' => {
+                let rest = chars.as_str().trim_start();
+                if rest.is_empty() || rest.starts_with('#') {
+                    break;
+                }
+                return Err(format!("unquoted whitespace before `{}`", rest.trim_end()));
+            }
+            c => value.push(c),
+        }
+    }
+    Ok(value)
+}
+
+/// Splits `.env` contents into logical lines, keeping newlines inside quotes, and
+/// yields each with the number of the physical line it starts on.
+struct LogicalLines<'a> {
+    rest: &'a str,
+    number: usize,
+}
+
+impl<'a> LogicalLines<'a> {
+    fn new(contents: &'a str) -> Self {
+        Self {
+            rest: contents,
+            number: 1,
+        }
+    }
+}
+
+impl<'a> Iterator for LogicalLines<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let mut quote = None;
+        let mut escaped = false;
+        let mut comment = false;
+        let mut end = self.rest.len();
+        for (index, c) in self.rest.char_indices() {
+            match (quote, c) {
+                (_, '\n') if quote.is_none() || comment => {
+                    end = index;
+                    break;
+                }
+                _ if comment => {}
+                (Some('"'), _) if escaped => escaped = false,
+                (Some('"'), '\\') => escaped = true,
+                (Some(open), c) if c == open => quote = None,
+                (Some(_), _) => {}
+                (None, _) if escaped => escaped = false,
+                (None, '\\') => escaped = true,
+                (None, '\'' | '"') => quote = Some(c),
+                (None, '#') => {
+                    comment = index == 0
+                        || self.rest[..index].ends_with([' ', '\t'])
+                        || self.rest[..index].trim().is_empty();
+                }
+                (None, _) => {}
+            }
+        }
+        let line = &self.rest[..end];
+        let number = self.number;
+        self.number += line.matches('\n').count() + 1;
+        self.rest = self.rest.get(end + 1..).unwrap_or("");
+        Some((number, line))
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::Violation;
+
+/// Errors that can occur while converting environment variables into TOML.
+///
+/// Variants caused by a single variable carry its name, see [`Error::var`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The variable name cannot be turned into a valid TOML key path.
+    InvalidKey { var: String, reason: String },
+    /// The variable name or value is not valid unicode.
+    NotUnicode { var: String },
+    /// Two variables map to the same TOML key, e.g. `APP_Port` and `APP_PORT`.
+    DuplicateKey { var: String, other: String },
+    /// A variable sets a value for a key that another variable uses as a table,
+    /// e.g. `APP_DB` and `APP_DB__HOST`.
+    KeyConflict { var: String, table_var: String },
+    /// A default value set with [`Converter::default_value`](crate::Converter::default_value)
+    /// is for a key that another default value uses as a table, e.g. `a` and `a.b`.
+    DefaultConflict { key: String, table_key: String },
+    /// Indexed variables such as `APP_HOSTS__0` do not form a valid array.
+    InvalidIndex { var: String, reason: String },
+    /// A variable holding JSON could not be parsed or contains a value, such as
+    /// `null`, that TOML cannot represent.
+    InvalidJson { var: String, reason: String },
+    /// A variable cannot be converted to the type its key has in the template,
+    /// such as `APP_PORT=abc` for an integer `port`. `expected` names the TOML type,
+    /// e.g. `integer` or `array`.
+    TypeMismatch { var: String, expected: String },
+    /// A `${...}` reference in a variable's value could not be expanded, because
+    /// it names an unset variable, forms a cycle or is malformed.
+    Interpolation { var: String, reason: String },
+    /// The converted variables could not be deserialized into the requested type.
+    /// `var` names the variable at the failing key, or the variable that would set
+    /// a missing field.
+    Deserialize { var: Option<String>, reason: String },
+    /// The configuration does not match the converter's schema. Holds every
+    /// violation found, each naming the variable it is about.
+    Validation { violations: Vec<Violation> },
+    /// A schema could not be loaded, or one of its patterns or defaults is invalid.
+    InvalidSchema { reason: String },
+    /// A TOML document could not be parsed.
+    InvalidToml { reason: String },
+    /// The file named by a variable with the converter's file suffix, such as
+    /// `APP_DB__PASSWORD_FILE`, could not be read or exceeds the size limit.
+    File {
+        var: String,
+        path: PathBuf,
+        reason: String,
+    },
+    /// Both a variable and the same variable with the file suffix are set, such
+    /// as `APP_DB__PASSWORD` and `APP_DB__PASSWORD_FILE`.
+    FileConflict { var: String, file_var: String },
+    /// A file could not be read.
+    Io { path: PathBuf, reason: String },
+    /// A `.env` input could not be parsed. `path` is `None` for in-memory contents.
+    Dotenv {
+        path: Option<PathBuf>,
+        line: Option<usize>,
+        reason: String,
+    },
+}
+
+impl Error {
+    /// Returns the name of the variable that caused the error, if there is one.
+    pub fn var(&self) -> Option<&str> {
+        match self {
+            Error::InvalidKey { var, .. }
+            | Error::NotUnicode { var }
+            | Error::DuplicateKey { var, .. }
+            | Error::KeyConflict { var, .. }
+            | Error::InvalidIndex { var, .. }
+            | Error::InvalidJson { var, .. }
+            | Error::TypeMismatch { var, .. }
+            | Error::Interpolation { var, .. } => Some(var),
+            Error::File { var, .. } | Error::FileConflict { var, .. } => Some(var),
+            Error::Deserialize { var, .. } => var.as_deref(),
+            Error::DefaultConflict { .. }
+            | Error::Validation { .. }
+            | Error::InvalidSchema { .. }
+            | Error::InvalidToml { .. }
+            | Error::Io { .. }
+            | Error::Dotenv { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidKey { var, reason } => {
+                write!(
+                    f,
+                    "environment variable `{}` has an invalid key: {}",
+                    var, reason
+                )
+            }
+            Error::NotUnicode { var } => {
+                write!(f, "environment variable `{}` is not valid unicode", var)
+            }
+            Error::DuplicateKey { var, other } => write!(
+                f,
+                "environment variables `{}` and `{}` map to the same key",
+                other, var
+            ),
+            Error::KeyConflict { var, table_var } => write!(
+                f,
+                "environment variable `{}` sets a value for a key that `{}` uses as a table",
+                var, table_var
+            ),
+            Error::DefaultConflict { key, table_key } => write!(
+                f,
+                "the default value for `{}` is for a key that the default value for `{}` uses as a table",
+                key, table_key
+            ),
+            Error::InvalidIndex { var, reason } => {
+                write!(
+                    f,
+                    "environment variable `{}` has an invalid array index: {}",
+                    var, reason
+                )
+            }
+            Error::InvalidJson { var, reason } => {
+                write!(
+                    f,
+                    "environment variable `{}` is not valid JSON: {}",
+                    var, reason
+                )
+            }
+            Error::TypeMismatch { var, expected } => write!(
+                f,
+                "environment variable `{}` must be a valid {}",
+                var, expected
+            ),
+            Error::Interpolation { var, reason } => write!(
+                f,
+                "environment variable `{}` could not be interpolated: {}",
+                var, reason
+            ),
+            Error::Deserialize { var, reason } => match var {
+                Some(var) => write!(
+                    f,
+                    "environment variable `{}` could not be deserialized: {}",
+                    var, reason
+                ),
+                None => write!(f, "could not deserialize the configuration: {}", reason),
+            },
+            Error::Validation { violations } => {
+                f.write_str("the configuration is invalid:")?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
+            Error::InvalidSchema { reason } => write!(f, "invalid schema: {}", reason),
+            Error::InvalidToml { reason } => write!(f, "invalid TOML: {}", reason),
+            Error::File { var, path, reason } => write!(
+                f,
+                "could not read `{}`, the file named by environment variable `{}`: {}",
+                path.display(),
+                var,
+                reason
+            ),
+            Error::FileConflict { var, file_var } => write!(
+                f,
+                "environment variables `{}` and `{}` are both set, but only one of them may be",
+                var, file_var
+            ),
+            Error::Io { path, reason } => {
+                write!(f, "could not read `{}`: {}", path.display(), reason)
+            }
+            Error::Dotenv { path, line, reason } => {
+                match path {
+                    Some(path) => write!(f, "{}", path.display())?,
+                    None => f.write_str("<.env contents>")?,
+                }
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                write!(f, ": {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::{Converter, Error};
+
+/// Replaces each selected variable ending with the converter's file suffix, such
+/// as `APP_DB__PASSWORD_FILE`, by the variable without the suffix holding the
+/// trimmed contents of the file it names. Interpolation, if enabled, has already
+/// expanded the paths.
+pub(crate) fn read(
+    converter: &Converter,
+    vars: Vec<(String, String)>,
+) -> Result<Vec<(String, String)>, Error> {
+    let Some(suffix) = converter.file_suffix.as_deref() else {
+        return Ok(vars);
+    };
+    let names: HashSet<&str> = vars.iter().map(|(var, _)| var.as_str()).collect();
+    let mut read = Vec::with_capacity(vars.len());
+    for (var, value) in &vars {
+        let target = match var.strip_suffix(suffix) {
+            Some(target) if converter.match_prefix(var).is_some() => target,
+            _ => {
+                read.push((var.clone(), value.clone()));
+                continue;
+            }
+        };
+        if names.contains(target) {
+            return Err(Error::FileConflict {
+                var: target.to_string(),
+                file_var: var.clone(),
+            });
+        }
+        let contents = read_value(var, value, converter.file_size_limit)?;
+        read.push((target.to_string(), contents));
+    }
+    Ok(read)
+}
+
+/// Reads the file at `path`, named by the variable `var`, and trims its contents.
+pub(crate) fn read_value(var: &str, path: &str, limit: u64) -> Result<String, Error> {
+    let error = |reason: String| Error::File {
+        var: var.to_string(),
+        path: PathBuf::from(path),
+        reason,
+    };
+    let file = File::open(path).map_err(|err| error(err.to_string()))?;
+    let mut contents = Vec::new();
+    file.take(limit.saturating_add(1))
+        .read_to_end(&mut contents)
+        .map_err(|err| error(err.to_string()))?;
+    if contents.len() as u64 > limit {
+        return Err(error(format!("the file is larger than {} bytes", limit)));
+    }
+    let contents = String::from_utf8(contents)
+        .map_err(|_| error("the file is not valid UTF-8".to_string()))?;
+    Ok(contents.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_file_vars() {
+        let dir = env::temp_dir().join(format!("envmtotoml-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let secret = dir.join("db_password");
+        fs::write(&secret, "hunter2\n").unwrap();
+        let secret = secret.to_str().unwrap();
+
+        let converter = Converter::new().prefix("APP_").file_suffix("_FILE");
+        let toml = converter
+            .convert_vars([
+                ("APP_DB__PASSWORD_FILE", secret),
+                ("APP_DB__USER", "admin"),
+                ("OTHER_FILE", "/missing"),
+            ])
+            .unwrap();
+        assert_eq!(toml, "\n[db]\npassword = \"hunter2\"\nuser = \"admin\"\n");
+
+        let err = converter
+            .convert_vars([("APP_DB__PASSWORD", "x"), ("APP_DB__PASSWORD_FILE", secret)])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::FileConflict {
+                var: "APP_DB__PASSWORD".to_string(),
+                file_var: "APP_DB__PASSWORD_FILE".to_string(),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "environment variables `APP_DB__PASSWORD` and `APP_DB__PASSWORD_FILE` are both \
+             set, but only one of them may be"
+        );
+
+        // References resolve to file contents, and paths may hold references.
+        let toml = converter
+            .clone()
+            .interpolate(true)
+            .convert_vars([
+                ("APP_URL", "u:${APP_DB__PASSWORD}@h"),
+                ("SECRETS", dir.to_str().unwrap()),
+                ("APP_DB__PASSWORD_FILE", "${SECRETS}/db_password"),
+            ])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "url = \"u:hunter2@h\"\n\n[db]\npassword = \"hunter2\"\n"
+        );
+
+        let err = converter
+            .clone()
+            .file_size_limit(4)
+            .convert_vars([("APP_TOKEN_FILE", secret)])
+            .unwrap_err();
+        assert!(matches!(err, Error::File { ref var, .. } if var == "APP_TOKEN_FILE"));
+        assert!(err.to_string().ends_with("the file is larger than 4 bytes"));
+
+        let missing = dir.join("missing");
+        let err = converter
+            .convert_vars([("APP_TOKEN_FILE", missing.to_str().unwrap())])
+            .unwrap_err();
+        assert_eq!(err.var(), Some("APP_TOKEN_FILE"));
+
+        let toml = Converter::new()
+            .prefix("APP_")
+            .convert_vars([("APP_LOG_FILE", "/var/log/app.log")])
+            .unwrap();
+        assert_eq!(toml, "log_file = \"/var/log/app.log\"\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use crate::{files, Converter, Error};
+
+/// Expands `${VAR}` references in the values of the variables the converter
+/// selects.
+///
+/// References are resolved against the whole source, so they may name variables
+/// without the prefix. `${VAR:-default}` falls back to `default` when `VAR` is
+/// unset or empty, `${VAR:?message}` fails with `message` instead, and `$$`
+/// stands for a literal `$`. With a [`Converter::file_suffix`], a reference to
+/// an unset `VAR` gives the contents of the file named by `VAR_FILE`, which are
+/// not expanded themselves.
+pub(crate) fn expand(
+    converter: &Converter,
+    vars: Vec<(String, String)>,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut resolver = Resolver {
+        converter,
+        raw: vars
+            .iter()
+            .map(|(var, value)| (var.as_str(), value.as_str()))
+            .collect(),
+        resolved: HashMap::new(),
+        stack: Vec::new(),
+    };
+    let mut expanded = Vec::with_capacity(vars.len());
+    for (var, value) in &vars {
+        let value = match converter.match_prefix(var) {
+            Some(_) => {
+                resolver.stack.push(var.clone());
+                let value = resolver.expand(value)?;
+                resolver.stack.pop();
+                value
+            }
+            None => value.clone(),
+        };
+        expanded.push((var.clone(), value));
+    }
+    Ok(expanded)
+}
+
+struct Resolver<'a> {
+    converter: &'a Converter,
+    raw: HashMap<&'a str, &'a str>,
+    resolved: HashMap<String, String>,
+    /// The variables being expanded, innermost last.
+    stack: Vec<String>,
+}
+
+impl Resolver<'_> {
+    fn error(&self, reason: String) -> Error {
+        Error::Interpolation {
+            var: self.stack.last().cloned().unwrap_or_default(),
+            reason,
+        }
+    }
+
+    /// Returns the expanded value of `name`, or `None` if it is not set.
+    fn resolve(&mut self, name: &str) -> Result<Option<String>, Error> {
+        if let Some(value) = self.resolved.get(name) {
+            return Ok(Some(value.clone()));
+        }
+        if let Some(start) = self.stack.iter().position(|var| var == name) {
+            let cycle: Vec<String> = self.stack[start..]
+                .iter()
+                .chain([&name.to_string()])
+                .map(|var| format!("`{}`", var))
+                .collect();
+            return Err(self.error(format!("reference cycle {}", cycle.join(" -> "))));
+        }
+        let Some(raw) = self.raw.get(name).copied() else {
+            return self.resolve_file(name);
+        };
+        self.stack.push(name.to_string());
+        let value = self.expand(raw)?;
+        self.stack.pop();
+        self.resolved.insert(name.to_string(), value.clone());
+        Ok(Some(value))
+    }
+
+    /// Returns the trimmed contents of the file named by the variable `name` with
+    /// the file suffix, if that variable is set.
+    fn resolve_file(&mut self, name: &str) -> Result<Option<String>, Error> {
+        let Some(suffix) = &self.converter.file_suffix else {
+            return Ok(None);
+        };
+        let file_var = format!("{}{}", name, suffix);
+        if self.converter.match_prefix(&file_var).is_none() {
+            return Ok(None);
+        }
+        let Some(path) = self.resolve(&file_var)? else {
+            return Ok(None);
+        };
+        let value = files::read_value(&file_var, &path, self.converter.file_size_limit)?;
+        self.resolved.insert(name.to_string(), value.clone());
+        Ok(Some(value))
+    }
+
+    /// Expands the references in `raw`, the value of the innermost variable on the
+    /// stack or a default within it.
+    fn expand(&mut self, raw: &str) -> Result<String, Error> {
+        let mut out = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(start) = rest.find('$') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(after) = rest.strip_prefix("$$") {
+                out.push('$');
+                rest = after;
+            } else if rest.starts_with("${") {
+                let Some(len) = closing_brace(rest) else {
+                    return Err(self.error(format!("unterminated reference `{}`", rest)));
+                };
+                out.push_str(&self.reference(&rest[2..len])?);
+                rest = &rest[len + 1..];
+            } else {
+                out.push('$');
+                rest = &rest[1..];
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Expands the body of a `${...}` reference.
+    fn reference(&mut self, body: &str) -> Result<String, Error> {
+        let name_len = body
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(body.len());
+        let (name, operator) = body.split_at(name_len);
+        if name.is_empty() {
+            return Err(self.error(format!("invalid reference `${{{}}}`", body)));
+        }
+        let value = self.resolve(name)?;
+        let non_empty = value.clone().filter(|value| !value.is_empty());
+        if let Some(default) = operator.strip_prefix(":-") {
+            return match non_empty {
+                Some(value) => Ok(value),
+                None => self.expand(default),
+            };
+        }
+        if let Some(message) = operator.strip_prefix(":?") {
+            return non_empty.ok_or_else(|| match message {
+                "" => self.error(format!("`{}` is not set", name)),
+                message => self.error(format!("`{}` is not set: {}", name, message)),
+            });
+        }
+        if !operator.is_empty() {
+            return Err(self.error(format!("invalid reference `${{{}}}`", body)));
+        }
+        match value {
+            Some(value) => Ok(value),
+            None => Err(self.error(format!("`${{{}}}` refers to an unset variable", name))),
+        }
+    }
+}
+
+/// Returns the index of the `}` closing the reference at the start of `text`,
+/// allowing references nested in defaults.
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dotenv;
+
+    fn expand_vars(vars: &[(&str, &str)]) -> Result<Vec<(String, String)>, Error> {
+        let vars = vars
+            .iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect();
+        expand(&Converter::new().prefix("APP_"), vars)
+    }
+
+    #[test]
+    fn test_expand() {
+        let vars = expand_vars(&[
+            ("USER", "admin"),
+            (
+                "APP_DB__URL",
+                "postgres://${APP_DB__USER}@${APP_DB__HOST}/db",
+            ),
+            ("APP_DB__USER", "${USER}"),
+            ("APP_DB__HOST", "${DB_HOST:-localhost:${PORT:-5432}}"),
+            ("APP_PRICE", "$$5 or $x"),
+            ("OTHER", "${UNSET}"),
+        ])
+        .unwrap();
+        assert_eq!(
+            vars[1].1, "postgres://admin@localhost:5432/db",
+            "references resolve in any order"
+        );
+        assert_eq!(vars[4].1, "$5 or $x");
+        assert_eq!(vars[5].1, "${UNSET}");
+
+        let err = expand_vars(&[("APP_A", "${APP_B}"), ("APP_B", "x${APP_A}")]).unwrap_err();
+        assert_eq!(
+            err,
+            Error::Interpolation {
+                var: "APP_B".to_string(),
+                reason: "reference cycle `APP_A` -> `APP_B` -> `APP_A`".to_string(),
+            }
+        );
+        let err = expand_vars(&[("APP_A", "${MISSING}")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "environment variable `APP_A` could not be interpolated: \
+             `${MISSING}` refers to an unset variable"
+        );
+        let err = expand_vars(&[("APP_A", "${TOKEN:?set it in .env}")]).unwrap_err();
+        assert_eq!(
+            err,
+            Error::Interpolation {
+                var: "APP_A".to_string(),
+                reason: "`TOKEN` is not set: set it in .env".to_string(),
+            }
+        );
+        let toml = Converter::new()
+            .prefix("APP_")
+            .interpolate(true)
+            .convert_vars([("APP_PORT", "${PORT:-8080}"), ("PORT", "")])
+            .unwrap();
+        assert_eq!(toml, "port = 8080\n");
+
+        let dotenv = Dotenv::new().contents(
+            "APP_HOST=db\nAPP_URL=${APP_HOST:-x}/y\nAPP_D=\"${UNSET:-fallback}\"\nAPP_P=$$5\n",
+        );
+        let toml = Converter::new()
+            .prefix("APP_")
+            .interpolate(true)
+            .convert_source(&dotenv)
+            .unwrap();
+        assert_eq!(
+            toml,
+            "d = \"fallback\"\nhost = \"db\"\np = \"$5\"\nurl = \"db/y\"\n"
+        );
+
+        assert!(expand_vars(&[("APP_A", "${B")]).is_err());
+        assert!(expand_vars(&[("APP_A", "${B:+x}")]).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::table::{Leaf, Node, Table};
+use crate::value::Value;
+use crate::Error;
+
+/// Whether `raw` looks like a JSON object or array, used when detecting JSON values.
+pub(crate) fn looks_like_json(raw: &str) -> bool {
+    let raw = raw.trim_start();
+    raw.starts_with('{') || raw.starts_with('[')
+}
+
+/// Parses the JSON value of `var` into a node of the configuration tree.
+///
+/// Objects become tables, arrays become arrays, and strings are kept as strings
+/// without type inference. `null` has no TOML equivalent and is rejected.
+pub(crate) fn parse(raw: &str, var: &str, position: usize) -> Result<Node, Error> {
+    let json: serde_json::Value = serde_json::from_str(raw).map_err(|err| Error::InvalidJson {
+        var: var.to_string(),
+        reason: err.to_string(),
+    })?;
+    to_node(json, &mut Vec::new(), var, position)
+}
+
+fn to_node(
+    json: serde_json::Value,
+    path: &mut Vec<String>,
+    var: &str,
+    position: usize,
+) -> Result<Node, Error> {
+    let invalid = |path: &[String], reason: &str| Error::InvalidJson {
+        var: var.to_string(),
+        reason: if path.is_empty() {
+            reason.to_string()
+        } else {
+            format!("{} at `{}`", reason, path.join("."))
+        },
+    };
+    let value = match json {
+        serde_json::Value::Null => {
+            return Err(invalid(path, "null cannot be represented in TOML"));
+        }
+        serde_json::Value::Bool(value) => Value::Boolean(value),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(value) => Value::Integer(value),
+            None if number.is_u64() => {
+                return Err(invalid(path, "integer is too large for TOML"));
+            }
+            None => Value::Float(number.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(value) => Value::String(value),
+        serde_json::Value::Array(elements) => {
+            let mut nodes = Vec::with_capacity(elements.len());
+            for (index, element) in elements.into_iter().enumerate() {
+                path.push(index.to_string());
+                nodes.push(to_node(element, path, var, position)?);
+                path.pop();
+            }
+            return Ok(Node::Array(nodes));
+        }
+        serde_json::Value::Object(object) => {
+            let mut table = Table::literal();
+            for (key, element) in object {
+                path.push(key.clone());
+                let node = to_node(element, path, var, position)?;
+                path.pop();
+                table.push(key, node);
+            }
+            return Ok(Node::Table(table));
+        }
+    };
+    Ok(Node::Leaf(Leaf {
+        value,
+        var: var.to_string(),
+        position,
+        default: false,
+        raw: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse(r#"{"a": {"b": [1, null]}}"#, "APP_X", 0).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "environment variable `APP_X` is not valid JSON: null cannot be represented in TOML at `a.b.1`"
+        );
+        let err = parse("{\"a\": 1", "APP_X", 0).unwrap_err();
+        assert_eq!(err.var(), Some("APP_X"));
+        assert!(matches!(err, Error::InvalidJson { .. }));
+        assert!(parse("18446744073709551615", "APP_X", 0).is_err());
+        assert!(looks_like_json("  [1]"));
+        assert!(!looks_like_json("x[1]"));
+    }
+}
@@ -1,73 +1,213 @@
-use std::collections::HashMap;
-use std::env;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+
+mod converter;
+mod de;
+mod defaults;
+mod dotenv;
+mod error;
+mod files;
+mod interpolate;
+mod json;
+mod merge;
+mod pattern;
+mod redact;
+mod reverse;
+mod schema;
+mod ser;
+mod source;
+mod table;
+mod template;
+mod value;
+
+pub use converter::{
+    ArrayMerge, ConflictPolicy, Converter, KeyCase, KeyOrder, ListDelimiter, TableMerge, TableStyle,
+};
+pub use dotenv::Dotenv;
+pub use error::Error;
+use pattern::strip_suffix_ignore_case;
+pub use redact::Redaction;
+pub use reverse::EnvFormat;
+pub use schema::{Field, FieldType, Schema, Violation};
+pub use source::{Env, VarSource};
+use table::{Leaf, Node, Table};
+use template::Template;
+use value::Value;
 
 /// Represents a single configuration item, which may belong to a section.
 #[derive(Debug, Clone)]
 struct ConfigItem {
-    section: Option<String>,
+    /// Table path of the item; empty for top-level keys.
+    section: Vec<String>,
     key: String,
-    value: String,
+    /// A single value, or an array for variables split into lists.
+    value: Node,
+    /// Name of the environment variable the item was read from.
+    var: String,
 }
 
-/// Organizes configuration items into sections for TOML format output.
+/// Organizes configuration items into a tree of nested tables for TOML format output.
 #[derive(Debug, Default)]
 struct Config {
-    global: Vec<ConfigItem>,
-    sections: HashMap<String, Vec<ConfigItem>>,
+    root: Table,
 }
 
 impl Config {
-    /// Parses environment variables with a given prefix into a structured `Config`.
-    fn from_env(prefix: &str) -> Self {
-        let mut config = Self::default();
-        for (key, value) in env::vars() {
-            if let Some(stripped_key) = key.strip_prefix(prefix) {
-                let normalized_key = stripped_key.to_lowercase();
-                let parts: Vec<&str> = normalized_key.split("__").collect();
-                let (section_parts, key) = parts.split_at(parts.len().saturating_sub(1));
-                let section = section_parts.join(".");
-
-                let config_item = ConfigItem {
-                    section: if section.is_empty() {
-                        None
-                    } else {
-                        Some(section)
-                    },
-                    key: key.join(""),
-                    value,
-                };
+    /// Parses the variables of `source` matching the converter's prefix into a structured `Config`.
+    ///
+    /// Fails if a variable name produces an empty table or key segment, such as
+    /// `APP___X` or a variable named exactly like the prefix, if two variables map
+    /// to the same key, if a value does not fit the type its key has in the
+    /// converter's template, if the configuration violates the converter's schema,
+    /// or if a matching variable cannot be read. Keys that are
+    /// also used as tables are resolved according to the converter's [`ConflictPolicy`].
+    fn from_source<S: VarSource + ?Sized>(
+        converter: &Converter,
+        source: &S,
+    ) -> Result<Self, Error> {
+        let template = converter
+            .template
+            .as_deref()
+            .map(Template::parse)
+            .transpose()?;
+        let mut items = Vec::new();
+        let mut violations = Vec::new();
+        let mut vars = source::collect(converter, source)?;
+        if converter.interpolate {
+            vars = interpolate::expand(converter, vars)?;
+        }
+        vars = files::read(converter, vars)?;
+        for (position, (var, value)) in vars.into_iter().enumerate() {
+            let Some((root, mut stripped_key)) = converter.match_prefix(&var) else {
+                continue;
+            };
 
-                if config_item.section.is_some() {
-                    config
-                        .sections
-                        .entry(config_item.section.clone().unwrap())
-                        .or_default()
-                        .push(config_item);
-                } else {
-                    config.global.push(config_item);
+            let mut delimiter = converter.list_delimiter_for(&var);
+            if let Some((suffix, suffix_delimiter)) = &converter.list_suffix {
+                if let Some(list_key) = strip_suffix_ignore_case(stripped_key, suffix) {
+                    stripped_key = list_key;
+                    delimiter = Some(*suffix_delimiter);
                 }
             }
-        }
-        config
-    }
+            let mut parts = root;
+            if converter.separator.is_empty() {
+                parts.push(converter.convert_segment(stripped_key));
+            } else {
+                parts.extend(
+                    stripped_key
+                        .split(converter.separator.as_str())
+                        .map(|segment| converter.convert_segment(segment)),
+                );
+            }
+            if parts.iter().any(String::is_empty) {
+                return Err(Error::InvalidKey {
+                    var,
+                    reason: "empty table or key segment".to_string(),
+                });
+            }
 
-    /// Converts the structured `Config` into a TOML-formatted string.
-    fn to_toml(&self) -> String {
-        let mut result = String::new();
+            let forced_string = converter.string_vars.contains(&var);
+            let typed = |raw: &str| {
+                let value = if converter.infer_types && !forced_string {
+                    Value::infer(raw)
+                } else {
+                    Value::String(raw.to_string())
+                };
+                Node::Leaf(Leaf {
+                    value,
+                    var: var.clone(),
+                    position,
+                    default: false,
+                    raw: Some(raw.to_string()),
+                })
+            };
+            let schema_type = converter
+                .schema
+                .as_ref()
+                .filter(|_| !forced_string)
+                .and_then(|schema| schema.expected(&parts));
+            let expected = schema_type.as_ref().or_else(|| {
+                template
+                    .as_ref()
+                    .filter(|_| !forced_string)
+                    .and_then(|template| template.get(&parts))
+            });
+            let value = match (converter.json_mode(&var, &value), expected, delimiter) {
+                (Some(true), _, _) => json::parse(&value, &var, position)?,
+                (_, Some(expected), _) => {
+                    match template::coerce(expected, &value, &var, position, delimiter) {
+                        Ok(node) => node,
+                        // With a schema, type errors are reported with all other
+                        // violations, keeping the value so it is not also reported missing.
+                        Err(Error::TypeMismatch { expected, .. }) if converter.schema.is_some() => {
+                            violations.push(Violation {
+                                var: var.clone(),
+                                key: parts.join("."),
+                                message: format!("must be a valid {}", expected),
+                            });
+                            Node::Leaf(Leaf {
+                                value: Value::String(value.clone()),
+                                var: var.clone(),
+                                position,
+                                default: false,
+                                raw: Some(value.clone()),
+                            })
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+                (Some(false), None, None) => {
+                    json::parse(&value, &var, position).unwrap_or_else(|_| typed(&value))
+                }
+                (_, None, Some(delimiter)) => {
+                    Node::Array(delimiter.split(&value).iter().map(|e| typed(e)).collect())
+                }
+                (None, None, None) => typed(&value),
+            };
+            let key = parts.pop().unwrap_or_default();
 
-        // Add global configuration items.
-        for item in &self.global {
-            result.push_str(&format!("{} = \"{}\"\n", item.key, item.value));
+            items.push(ConfigItem {
+                section: parts,
+                key,
+                value,
+                var,
+            });
         }
 
-        // Add sectioned configuration items.
-        for (section, items) in &self.sections {
-            result.push_str(&format!("\n[{}]\n", section));
-            for item in items {
-                result.push_str(&format!("{} = \"{}\"\n", item.key, item.value));
-            }
+        // Inserting in name order keeps conflict errors independent of source order;
+        // output order is decided by each item's position instead.
+        items.sort_by(|a, b| a.var.cmp(&b.var));
+        let mut config = Self::default();
+        for item in items {
+            config.insert(item, &converter.conflict_policy)?;
+        }
+        if converter.indexed_arrays {
+            config.root.build_arrays()?;
+        }
+        defaults::apply(converter, &mut config.root, template.as_ref())?;
+        if let Some(schema) = &converter.schema {
+            schema.validate(converter, &mut config.root, &mut violations)?;
+        }
+        if !violations.is_empty() {
+            return Err(Error::Validation { violations });
         }
+        Ok(config)
+    }
+
+    /// Adds an item to the tree, resolving keys that are also used as tables,
+    /// such as `APP_DB` alongside `APP_DB__HOST`, according to `policy`.
+    fn insert(&mut self, item: ConfigItem, policy: &ConflictPolicy) -> Result<(), Error> {
+        self.root
+            .insert(&item.section, item.key, item.value, policy)
+    }
 
+    /// Converts the structured `Config` into a TOML-formatted string, ordering and
+    /// styling tables as configured on the converter.
+    fn to_toml(&self, converter: &Converter) -> String {
+        let mut result = String::new();
+        self.root
+            .write_toml(&mut result, &mut Vec::new(), converter);
         result
     }
 }
@@ -80,14 +220,134 @@ impl Config {
 ///
 /// # Returns
 ///
-/// A `Result` which is either a `String` containing the TOML representation or an error message.
-pub fn env_to_toml(prefix: &str) -> Result<String, String> {
-    let config = Config::from_env(prefix);
-    Ok(config.to_toml())
+/// A `Result` which is either a `String` containing the TOML representation or an [`Error`]
+/// naming the environment variable that could not be converted.
+pub fn env_to_toml(prefix: &str) -> Result<String, Error> {
+    Converter::new().prefix(prefix).convert()
+}
+
+/// Converts `(name, value)` pairs with a specified prefix into a TOML string.
+///
+/// Accepts anything iterable as pairs, such as a `HashMap<String, String>` or a
+/// captured snapshot of `std::env::vars()`, without touching the process environment.
+///
+/// # Arguments
+///
+/// * `prefix` - A string slice that holds the prefix for filtering variables.
+/// * `vars` - The variables to convert.
+///
+/// # Returns
+///
+/// A `Result` which is either a `String` containing the TOML representation or an [`Error`]
+/// naming the variable that could not be converted.
+pub fn vars_to_toml<I, K, V>(prefix: &str, vars: I) -> Result<String, Error>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Into<String>,
+    V: Into<String>,
+{
+    Converter::new().prefix(prefix).convert_vars(vars)
+}
+
+/// Converts the variables of a [`VarSource`] with a specified prefix into a TOML string.
+///
+/// # Arguments
+///
+/// * `prefix` - A string slice that holds the prefix for filtering variables.
+/// * `source` - The provider of the variables to convert.
+///
+/// # Returns
+///
+/// A `Result` which is either a `String` containing the TOML representation or an [`Error`]
+/// naming the variable that could not be converted.
+pub fn source_to_toml<S: VarSource + ?Sized>(prefix: &str, source: &S) -> Result<String, Error> {
+    Converter::new().prefix(prefix).convert_source(source)
+}
+
+/// Converts the variables of a `.env` file with a specified prefix into a TOML string.
+///
+/// The file is parsed directly and never loaded into the process environment. Use
+/// [`Dotenv`] with [`source_to_toml`] to layer several files.
+///
+/// # Arguments
+///
+/// * `path` - The path of the `.env` file.
+/// * `prefix` - A string slice that holds the prefix for filtering variables.
+///
+/// # Returns
+///
+/// A `Result` which is either a `String` containing the TOML representation or an [`Error`]
+/// naming the variable, or the file and line, that could not be converted.
+pub fn dotenv_file_to_toml(path: impl AsRef<Path>, prefix: &str) -> Result<String, Error> {
+    source_to_toml(prefix, &Dotenv::new().file(path.as_ref()))
+}
+
+/// Converts the variables of `.env` formatted contents with a specified prefix into a TOML string.
+///
+/// # Arguments
+///
+/// * `contents` - The contents of a `.env` file.
+/// * `prefix` - A string slice that holds the prefix for filtering variables.
+///
+/// # Returns
+///
+/// A `Result` which is either a `String` containing the TOML representation or an [`Error`]
+/// naming the variable, or the line, that could not be converted.
+pub fn dotenv_str_to_toml(contents: &str, prefix: &str) -> Result<String, Error> {
+    source_to_toml(prefix, &Dotenv::new().contents(contents))
+}
+
+/// Deserializes environment variables with a specified prefix directly into `T`.
+///
+/// # Arguments
+///
+/// * `prefix` - A string slice that holds the prefix for filtering environment variables.
+///
+/// # Returns
+///
+/// A `Result` which is either the deserialized `T` or an [`Error`] naming the environment
+/// variable that could not be converted or deserialized.
+pub fn from_env<T: DeserializeOwned>(prefix: &str) -> Result<T, Error> {
+    Converter::new().prefix(prefix).deserialize()
+}
+
+/// Applies environment variables with a specified prefix on top of an existing TOML document.
+///
+/// # Arguments
+///
+/// * `prefix` - A string slice that holds the prefix for filtering environment variables.
+/// * `toml` - The TOML document to merge the variables into.
+///
+/// # Returns
+///
+/// A `Result` which is either a `String` containing the merged document, with comments and
+/// formatting of untouched keys preserved, or an [`Error`] if the document is not valid TOML
+/// or a variable cannot be converted.
+pub fn merge_env_into_toml(prefix: &str, toml: &str) -> Result<String, Error> {
+    Converter::new().prefix(prefix).merge(toml)
+}
+
+/// Converts a TOML document into environment variables with a specified prefix, the
+/// inverse of [`env_to_toml`].
+///
+/// # Arguments
+///
+/// * `prefix` - A string slice that holds the prefix for the variable names.
+/// * `toml` - The TOML document to convert.
+///
+/// # Returns
+///
+/// A `Result` which is either the `(name, value)` variables in document order, ready to
+/// be written with an [`EnvFormat`], or an [`Error`] if the document is not valid TOML
+/// or has a key, such as `max-conn`, that gives no valid variable name.
+pub fn toml_to_env(prefix: &str, toml: &str) -> Result<Vec<(String, String)>, Error> {
+    Converter::new().prefix(prefix).to_env(toml)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::env;
     use std::fs::File;
     use std::io::Write;
 
@@ -106,6 +366,690 @@ mod tests {
         write_to_file("config.toml", &toml_content).expect("Failed to write TOML to file");
         println!("TOML content written to config.toml:\n{}", toml_content);
     }
+
+    #[test]
+    fn test_converter_infers_and_forces_strings() {
+        let vars = [
+            ("APP_PORT", "8080"),
+            ("APP_DEBUG", "true"),
+            ("APP_VERSION", "1.10"),
+        ];
+
+        let typed = Converter::new()
+            .prefix("APP_")
+            .force_string("APP_VERSION")
+            .convert_vars(vars)
+            .unwrap();
+        assert!(typed.contains("port = 8080\n"));
+        assert!(typed.contains("debug = true\n"));
+        assert!(typed.contains("version = \"1.10\"\n"));
+
+        let untyped = Converter::new()
+            .prefix("APP_")
+            .infer_types(false)
+            .convert_vars(vars)
+            .unwrap();
+        assert!(untyped.contains("port = \"8080\"\n"));
+        assert!(untyped.contains("debug = \"true\"\n"));
+    }
+
+    #[test]
+    fn test_converter_quotes_keys_and_rejects_empty_segments() {
+        let converter = Converter::new().prefix("APP_");
+        let toml = converter
+            .convert_vars([("APP_A__B.C__WEIRD.KEY", "x")])
+            .unwrap();
+        assert_eq!(toml, "\n[a.\"b.c\"]\n\"weird.key\" = \"x\"\n");
+
+        let err = converter.convert_vars([("APP___X", "x")]).unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidKey {
+                var: "APP___X".to_string(),
+                reason: "empty table or key segment".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_converter_reports_duplicate_keys() {
+        let err = Converter::new()
+            .prefix("APP_")
+            .convert_vars([("APP_Port", "1"), ("APP_PORT", "2")])
+            .unwrap_err();
+        assert!(matches!(err, Error::DuplicateKey { .. }));
+        assert!(err.to_string().contains("APP_Port"));
+        assert!(err.to_string().contains("APP_PORT"));
+    }
+
+    #[test]
+    fn test_converter_resolves_key_table_conflicts() {
+        let vars = [
+            ("APP_DB", "x"),
+            ("APP_DB__HOST", "y"),
+            ("APP_DB__REPLICA__PORT", "5432"),
+            ("APP_DB__REPLICA", "z"),
+        ];
+        let converter = Converter::new().prefix("APP_");
+
+        let err = converter.convert_vars(vars).unwrap_err();
+        assert_eq!(
+            err,
+            Error::KeyConflict {
+                var: "APP_DB".to_string(),
+                table_var: "APP_DB__HOST".to_string(),
+            }
+        );
+
+        let toml = converter
+            .clone()
+            .conflict_policy(ConflictPolicy::TableWins)
+            .convert_vars(vars)
+            .unwrap();
+        assert!(!toml.contains("\"x\""));
+        assert!(!toml.contains("\"z\""));
+
+        let toml = converter
+            .conflict_policy(ConflictPolicy::ReservedKey("_value".to_string()))
+            .convert_vars(vars)
+            .unwrap();
+        assert!(toml.contains("[db]\n"));
+        assert!(toml.contains("_value = \"x\"\n"));
+        assert!(toml.contains("[db.replica]\n"));
+        assert!(toml.contains("_value = \"z\"\n"));
+    }
+
+    #[test]
+    fn test_vars_to_toml_without_process_environment() {
+        let toml = vars_to_toml("APP_", [("APP_PORT", "80"), ("OTHER_PORT", "81")]).unwrap();
+        assert_eq!(toml, "port = 80\n");
+
+        let mut vars = HashMap::new();
+        vars.insert("APP_DB__HOST".to_string(), "localhost".to_string());
+        assert_eq!(
+            vars_to_toml("APP_", vars).unwrap(),
+            "\n[db]\nhost = \"localhost\"\n"
+        );
+    }
+
+    #[test]
+    fn test_source_to_toml_ignores_errors_outside_prefix() {
+        struct Broken;
+
+        impl VarSource for Broken {
+            fn vars(&self) -> Box<dyn Iterator<Item = Result<(String, String), Error>> + '_> {
+                Box::new(
+                    [
+                        Ok(("APP_NAME".to_string(), "demo".to_string())),
+                        Err(Error::NotUnicode {
+                            var: "OTHER_BAD".to_string(),
+                        }),
+                    ]
+                    .into_iter(),
+                )
+            }
+        }
+
+        assert_eq!(
+            source_to_toml("APP_", &Broken).unwrap(),
+            "name = \"demo\"\n"
+        );
+        assert_eq!(
+            source_to_toml("OTHER_", &Broken).unwrap_err(),
+            Error::NotUnicode {
+                var: "OTHER_BAD".to_string()
+            }
+        );
+
+        let mut snapshot = BTreeMap::new();
+        snapshot.insert("APP_DEBUG", "true");
+        assert_eq!(source_to_toml("APP_", &snapshot).unwrap(), "debug = true\n");
+    }
+
+    #[test]
+    fn test_dotenv_str_to_toml() {
+        let contents = "# defaults\nAPP_PORT=8080\nexport APP_DB__HOST='db.local'\nOTHER=1\n";
+        assert_eq!(
+            dotenv_str_to_toml(contents, "APP_").unwrap(),
+            "port = 8080\n\n[db]\nhost = \"db.local\"\n"
+        );
+        assert!(env::var("APP_DB__HOST").is_err());
+
+        let err = dotenv_str_to_toml("APP_A=1\nAPP_B=\"ok\"\nAPP_C = x y\n", "APP_").unwrap_err();
+        assert!(
+            matches!(err, Error::Dotenv { line: Some(3), .. }),
+            "{:?}",
+            err
+        );
+
+        let err = dotenv_str_to_toml("# example: x y\nAPP_A=1\nAPP_B = x y\n", "APP_").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "<.env contents>:3: could not parse the value of `APP_B`: unquoted whitespace before `y`"
+        );
+        let err = dotenv_str_to_toml("APP_A='multi\nline'\nAPP_B=\"open\n", "APP_").unwrap_err();
+        assert!(
+            matches!(err, Error::Dotenv { line: Some(3), .. }),
+            "{:?}",
+            err
+        );
+
+        let contents = "APP_HOME=$HOME\nAPP_Q=\"${USER} \\$x\\n\" # note\nAPP_R='a'\"b\"c\r\n";
+        assert_eq!(
+            dotenv_str_to_toml(contents, "APP_").unwrap(),
+            "home = \"$HOME\"\nq = \"\"\"\n${USER} $x\n\"\"\"\nr = \"abc\"\n"
+        );
+
+        // Unlike dotenvy, `${VAR}` is not substituted from the process environment.
+        let contents = "APP_A=${HOME}\nAPP_B=\"${HOME}/data\"\n";
+        assert_eq!(
+            dotenv_str_to_toml(contents, "APP_").unwrap(),
+            "a = \"${HOME}\"\nb = \"${HOME}/data\"\n"
+        );
+    }
+
+    #[test]
+    fn test_dotenv_files_are_layered() {
+        let dir = env::temp_dir().join(format!("envmtotoml-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join(".env");
+        let local = dir.join(".env.local");
+        write_to_file(base.to_str().unwrap(), "APP_PORT=80\nAPP_NAME=base\n").unwrap();
+        write_to_file(local.to_str().unwrap(), "APP_PORT=8080\n\nAPP_BAD\n").unwrap();
+
+        assert_eq!(
+            dotenv_file_to_toml(&base, "APP_").unwrap(),
+            "name = \"base\"\nport = 80\n"
+        );
+        let err = source_to_toml("APP_", &Dotenv::new().file(&base).file(&local)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{}:3: could not parse `APP_BAD`", local.display())
+        );
+
+        write_to_file(local.to_str().unwrap(), "APP_PORT=8080\n").unwrap();
+        assert_eq!(
+            source_to_toml("APP_", &Dotenv::new().file(&base).file(&local)).unwrap(),
+            "name = \"base\"\nport = 8080\n"
+        );
+        assert!(matches!(
+            dotenv_file_to_toml(dir.join("missing"), "APP_"),
+            Err(Error::Io { .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn config_with(vars: &[(&str, &str)]) -> Config {
+        let mut config = Config::default();
+        for (position, (path, value)) in vars.iter().enumerate() {
+            let mut section: Vec<String> = path.split('.').map(str::to_string).collect();
+            let key = section.pop().unwrap();
+            let item = ConfigItem {
+                section,
+                key,
+                value: Node::Leaf(Leaf {
+                    value: Value::infer(value),
+                    var: path.to_string(),
+                    position,
+                    default: false,
+                    raw: Some(value.to_string()),
+                }),
+                var: path.to_string(),
+            };
+            config.insert(item, &ConflictPolicy::Error).unwrap();
+        }
+        config
+    }
+
+    #[test]
+    fn test_to_toml_key_orders() {
+        let config = config_with(&[
+            ("port", "1"),
+            ("db.port", "2"),
+            ("name", "a"),
+            ("cache.ttl", "3"),
+            ("db.host", "b"),
+        ]);
+
+        let ordered = |order: KeyOrder| config.to_toml(&Converter::new().key_order(order));
+        assert_eq!(
+            ordered(KeyOrder::Alphabetical),
+            "name = \"a\"\nport = 1\n\n[cache]\nttl = 3\n\n[db]\nhost = \"b\"\nport = 2\n"
+        );
+        assert_eq!(
+            ordered(KeyOrder::Discovery),
+            "port = 1\nname = \"a\"\n\n[db]\nport = 2\nhost = \"b\"\n\n[cache]\nttl = 3\n"
+        );
+        let priority = KeyOrder::Priority(vec!["db".to_string(), "db.port".to_string()]);
+        assert_eq!(
+            ordered(priority),
+            "name = \"a\"\nport = 1\n\n[db]\nport = 2\nhost = \"b\"\n\n[cache]\nttl = 3\n"
+        );
+    }
+
+    #[test]
+    fn test_to_toml_nested_tables() {
+        let config = config_with(&[
+            ("a.b.c", "1"),
+            ("z", "2"),
+            ("a.x", "3"),
+            ("a.b.d.e", "4"),
+            ("b.c.d", "5"),
+        ]);
+
+        assert_eq!(
+            config.to_toml(&Converter::new()),
+            "z = 2\n\n[a]\nx = 3\n\n[a.b]\nc = 1\n\n[a.b.d]\ne = 4\n\n[b.c]\nd = 5\n"
+        );
+        assert_eq!(
+            config.to_toml(&Converter::new().table_style(2, TableStyle::Dotted)),
+            "z = 2\n\n[a]\nb.c = 1\nb.d.e = 4\nx = 3\n\n[b]\nc.d = 5\n"
+        );
+        assert_eq!(
+            config.to_toml(&Converter::new().table_style(2, TableStyle::Inline)),
+            "z = 2\n\n[a]\nb = { c = 1, d = { e = 4 } }\nx = 3\n\n[b]\nc = { d = 5 }\n"
+        );
+        assert_eq!(
+            config.to_toml(&Converter::new().table_style(1, TableStyle::Dotted)),
+            "a.b.c = 1\na.b.d.e = 4\na.x = 3\nb.c.d = 5\nz = 2\n"
+        );
+    }
+
+    #[test]
+    fn test_indexed_arrays() {
+        let vars = [
+            ("APP_HOSTS__1", "b.local"),
+            ("APP_HOSTS__0", "a.local"),
+            ("APP_DB__PORTS__0", "5432"),
+            ("APP_DB__PORTS__1", "true"),
+        ];
+        assert_eq!(
+            vars_to_toml("APP_", vars).unwrap(),
+            "hosts = [\"a.local\", \"b.local\"]\n\n[db]\nports = [5432, true]\n"
+        );
+        assert_eq!(
+            Converter::new()
+                .prefix("APP_")
+                .indexed_arrays(false)
+                .convert_vars(vars[..2].to_vec())
+                .unwrap(),
+            "\n[hosts]\n0 = \"a.local\"\n1 = \"b.local\"\n"
+        );
+
+        let gap = vars_to_toml("APP_", [("APP_HOSTS__0", "a"), ("APP_HOSTS__2", "c")]);
+        assert_eq!(
+            gap.unwrap_err(),
+            Error::InvalidIndex {
+                var: "APP_HOSTS__2".to_string(),
+                reason: "array index 2 is used but index 1 is missing".to_string(),
+            }
+        );
+        let duplicate = vars_to_toml("APP_", [("APP_HOSTS__0", "a"), ("APP_HOSTS__00", "b")]);
+        assert!(matches!(duplicate, Err(Error::DuplicateKey { .. })));
+        let mixed = vars_to_toml("APP_", [("APP_HOSTS__0", "a"), ("APP_HOSTS__NAME", "b")]);
+        assert!(matches!(mixed, Err(Error::InvalidIndex { .. })));
+    }
+
+    #[test]
+    fn test_arrays_of_tables() {
+        let vars = [
+            ("APP_UPSTREAM__1__HOST", "b.local"),
+            ("APP_UPSTREAM__0__HOST", "a.local"),
+            ("APP_UPSTREAM__0__PORT", "80"),
+            ("APP_UPSTREAM__0__TLS__ENABLED", "true"),
+            ("APP_UPSTREAM__1__SERVERS__0__NAME", "s0"),
+            ("APP_UPSTREAM__1__SERVERS__1__NAME", "s1"),
+            ("APP_DB__REPLICAS__0__HOST", "r0"),
+        ];
+        let toml = vars_to_toml("APP_", vars).unwrap();
+        assert_eq!(
+            toml,
+            "\n[[db.replicas]]\nhost = \"r0\"\n\
+             \n[[upstream]]\nhost = \"a.local\"\nport = 80\n\n[upstream.tls]\nenabled = true\n\
+             \n[[upstream]]\nhost = \"b.local\"\n\n[[upstream.servers]]\nname = \"s0\"\n\
+             \n[[upstream.servers]]\nname = \"s1\"\n"
+        );
+
+        let parsed: toml::Table = toml.parse().unwrap();
+        let upstream = parsed["upstream"].as_array().unwrap();
+        assert_eq!(upstream.len(), 2);
+        assert_eq!(upstream[0]["tls"]["enabled"].as_bool(), Some(true));
+        assert_eq!(upstream[1]["servers"][1]["name"].as_str(), Some("s1"));
+
+        let inline = Converter::new()
+            .prefix("APP_")
+            .table_style(1, TableStyle::Inline)
+            .convert_vars(vars[..3].to_vec())
+            .unwrap();
+        assert_eq!(
+            inline,
+            "upstream = [{ host = \"a.local\", port = 80 }, { host = \"b.local\" }]\n"
+        );
+    }
+
+    #[test]
+    fn test_separators_and_prefixes() {
+        let vars = [
+            ("APP_DB_HOST", "h"),
+            ("app:Logging:Level", "debug"),
+            ("APP.SERVER.PORT", "80"),
+        ];
+        for (separator, expected) in [
+            ("_", "\n[db]\nhost = \"h\"\n"),
+            (":", "\n[logging]\nlevel = \"debug\"\n"),
+            (".", "\n[server]\nport = 80\n"),
+        ] {
+            let prefix = format!("APP{}", separator);
+            let toml = Converter::new()
+                .prefix(prefix)
+                .separator(separator)
+                .ignore_prefix_case(true)
+                .convert_vars(vars)
+                .unwrap();
+            assert_eq!(toml, expected);
+        }
+
+        let converter = Converter::new()
+            .prefix_table("APP_", "app")
+            .prefix_table("APP_DB_", "database")
+            .prefix_table("CACHE_", "services.cache")
+            .separator("_");
+        let toml = converter
+            .convert_vars([
+                ("APP_NAME", "x"),
+                ("APP_DB_HOST", "h"),
+                ("CACHE_TTL", "5"),
+                ("OTHER", "1"),
+            ])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "\n[app]\nname = \"x\"\n\n[database]\nhost = \"h\"\n\n[services.cache]\nttl = 5\n"
+        );
+
+        let toml = Converter::new()
+            .prefix("App__")
+            .keep_prefix(true)
+            .convert_vars([("App__PORT", "80"), ("app__PORT", "81")])
+            .unwrap();
+        assert_eq!(toml, "\n[app]\nport = 80\n");
+    }
+
+    #[test]
+    fn test_key_cases() {
+        for (case, expected) in [
+            (KeyCase::Preserve, "maxConnections"),
+            (KeyCase::Lower, "max_connections"),
+            (KeyCase::Snake, "max_connections"),
+            (KeyCase::Kebab, "max-connections"),
+            (KeyCase::Camel, "maxConnections"),
+            (KeyCase::Pascal, "MaxConnections"),
+        ] {
+            let segment = if case == KeyCase::Preserve {
+                "maxConnections"
+            } else {
+                "MAX_CONNECTIONS"
+            };
+            assert_eq!(case.apply(segment), expected, "{:?}", case);
+        }
+        assert_eq!(KeyCase::Snake.apply("HTTPServer2Port"), "http_server2_port");
+        assert_eq!(KeyCase::Camel.apply("api-key_ID"), "apiKeyId");
+        assert_eq!(KeyCase::Kebab.apply("0"), "0");
+
+        let toml = Converter::new()
+            .prefix("APP_")
+            .key_case(KeyCase::Camel)
+            .rename("DB", "database")
+            .convert_vars([
+                ("APP_DB__MAX_POOL", "5"),
+                ("APP_HTTP_SERVER__LISTEN_PORT", "80"),
+                ("APP_HOSTS__0__HOST_NAME", "a"),
+            ])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "\n[database]\nmaxPool = 5\n\n[[hosts]]\nhostName = \"a\"\n\n[httpServer]\nlistenPort = 80\n"
+        );
+    }
+
+    #[test]
+    fn test_toml_to_env() {
+        let toml = "name = \"svc\"\nports = [80, 443]\n\n[db]\nhost = \"h\"\n";
+        let vars = toml_to_env("APP_", toml).unwrap();
+        assert_eq!(
+            EnvFormat::Dotenv.format(&vars),
+            "APP_NAME=svc\nAPP_PORTS__0=80\nAPP_PORTS__1=443\nAPP_DB__HOST=h\n"
+        );
+        assert_eq!(vars_to_toml("APP_", vars).unwrap(), toml);
+    }
+
+    #[test]
+    fn test_template_coercion() {
+        let template = "\
+port = 8080
+ratio = 0.5
+debug = false
+name = \"svc\"
+started = 1979-05-27
+hosts = [\"a\"]
+weights = [1]
+
+[[servers]]
+port = 1
+";
+        let converter = Converter::new().prefix("APP_").template(template);
+        let toml = converter
+            .convert_vars([
+                ("APP_PORT", "80"),
+                ("APP_RATIO", "2"),
+                ("APP_DEBUG", "yes"),
+                ("APP_NAME", "123"),
+                ("APP_STARTED", "2024-01-02"),
+                ("APP_HOSTS", "x, y"),
+                ("APP_WEIGHTS", "3,4"),
+                ("APP_SERVERS__0__PORT", "0x10"),
+                ("APP_EXTRA", "true"),
+            ])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "debug = true\nextra = true\nhosts = [\"x\", \"y\"]\nname = \"123\"\nport = 80\nratio = 2.0\n\
+             started = 2024-01-02\nweights = [3, 4]\n\n[[servers]]\nport = 16\n"
+        );
+
+        let err = converter.convert_vars([("APP_PORT", "abc")]).unwrap_err();
+        assert_eq!(
+            err,
+            Error::TypeMismatch {
+                var: "APP_PORT".to_string(),
+                expected: "integer".to_string(),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "environment variable `APP_PORT` must be a valid integer"
+        );
+        let err = converter
+            .convert_vars([("APP_WEIGHTS", "1,x")])
+            .unwrap_err();
+        assert_eq!(err.var(), Some("APP_WEIGHTS"));
+        assert!(converter.convert_vars([("APP_SERVERS", "x")]).is_err());
+        assert!(Converter::new()
+            .template("port = ")
+            .convert_vars([("PORT", "1")])
+            .is_err());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let converter = Converter::new()
+            .prefix("APP_")
+            .defaults("name = \"svc\"\nports = [80]\n\n[db]\nhost = \"localhost\"\npool_size = 5\n")
+            .default_value("db.pool_size", "10")
+            .default_value("db.timeout", "2.5");
+        let toml = converter
+            .convert_vars([("APP_DB__HOST", "db.internal"), ("APP_PORTS__0", "443")])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "name = \"svc\"\nports = [443]\n\n[db]\nhost = \"db.internal\"\npool_size = 10\ntimeout = 2.5\n"
+        );
+
+        let toml = converter
+            .annotate_sources(true)
+            .key_order(KeyOrder::Discovery)
+            .convert_vars([("APP_DB__HOST", "db.internal")])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "name = \"svc\" # default\nports = [80] # default\n\n[db]\n\
+             host = \"db.internal\" # from APP_DB__HOST\npool_size = 10 # default\ntimeout = 2.5 # default\n"
+        );
+
+        let err = Converter::new()
+            .defaults("a = ")
+            .convert_vars([("A", "1")])
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidToml { .. }));
+
+        for converter in [
+            Converter::new()
+                .prefix("APP_")
+                .default_value("a", "1")
+                .default_value("a.b", "2"),
+            Converter::new()
+                .prefix("APP_")
+                .default_value("a.b", "2")
+                .default_value("a", "1"),
+        ] {
+            let err = converter.convert_vars([("APP_C", "3")]).unwrap_err();
+            assert_eq!(
+                err,
+                Error::DefaultConflict {
+                    key: "a".to_string(),
+                    table_key: "a.b".to_string(),
+                }
+            );
+            assert_eq!(err.var(), None);
+        }
+        let converter = Converter::new()
+            .default_value("a", "1")
+            .default_value("a", "2");
+        assert_eq!(
+            converter
+                .convert_vars(Vec::<(String, String)>::new())
+                .unwrap(),
+            "a = 2\n"
+        );
+    }
+
+    #[test]
+    fn test_json_values() {
+        let converter = Converter::new().prefix("APP_").json("APP_FEATURES");
+        let toml = converter
+            .convert_vars([
+                (
+                    "APP_FEATURES",
+                    r#"{"b": [1, 2.5], "a": true, "c": {"name": "x"}}"#,
+                ),
+                ("APP_FEATURES__D", "4"),
+                ("APP_TAGS", r#"["x"]"#),
+            ])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "tags = '[\"x\"]'\n\n[features]\na = true\nb = [1, 2.5]\nd = 4\n\n[features.c]\nname = \"x\"\n"
+        );
+        let parsed: toml::Table = toml.parse().unwrap();
+        assert_eq!(parsed["features"]["c"]["name"].as_str(), Some("x"));
+
+        let detecting = Converter::new().prefix("APP_").detect_json(true);
+        let toml = detecting
+            .convert_vars([
+                ("APP_SERVERS", r#"[{"host": "a"}, {"host": "b"}]"#),
+                ("APP_RANGE", "[1, 2"),
+            ])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "range = \"[1, 2\"\n\n[[servers]]\nhost = \"a\"\n\n[[servers]]\nhost = \"b\"\n"
+        );
+
+        let toml = converter
+            .convert_vars([
+                ("APP_FEATURES", r#"{"0": "a", "1": "b", "404": {"2": "c"}}"#),
+                ("APP_HOSTS__0", "h"),
+            ])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "hosts = [\"h\"]\n\n[features]\n0 = \"a\"\n1 = \"b\"\n\n[features.404]\n2 = \"c\"\n"
+        );
+
+        let err = converter
+            .convert_vars([("APP_FEATURES", "{oops}")])
+            .unwrap_err();
+        assert_eq!(err.var(), Some("APP_FEATURES"));
+        let err = converter
+            .convert_vars([("APP_FEATURES", r#"{"a": null}"#)])
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("null cannot be represented in TOML at `a`"));
+        let err = converter
+            .convert_vars([("APP_FEATURES", r#"{"a": 1}"#), ("APP_FEATURES__A", "2")])
+            .unwrap_err();
+        assert!(matches!(err, Error::DuplicateKey { .. }));
+    }
+
+    #[test]
+    fn test_delimited_lists() {
+        let converter = Converter::new()
+            .prefix("APP_")
+            .list("APP_*ORIGINS", ListDelimiter::Comma)
+            .list("APP_PORTS", ListDelimiter::Whitespace)
+            .list_suffix("__LIST", ListDelimiter::Semicolon);
+        let toml = converter
+            .convert_vars([
+                ("APP_ALLOWED_ORIGINS", " a.com, b\\,c.com ,"),
+                ("APP_PORTS", "80  443\t8080"),
+                ("APP_DB__HOSTS__LIST", "db1;db2"),
+                ("APP_NAME", "a,b"),
+            ])
+            .unwrap();
+        assert_eq!(
+            toml,
+            "allowed_origins = [\"a.com\", \"b,c.com\"]\nname = \"a,b\"\nports = [80, 443, 8080]\n\
+             \n[db]\nhosts = [\"db1\", \"db2\"]\n"
+        );
+        assert_eq!(
+            converter.convert_vars([("APP_PORTS", "")]).unwrap(),
+            "ports = []\n"
+        );
+        assert_eq!(
+            converter.convert_vars([("APP_L__list", "a;b")]).unwrap(),
+            "l = [\"a\", \"b\"]\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_converter_reports_non_unicode_values() {
+        use std::os::unix::ffi::OsStrExt;
+
+        env::set_var("ENVTOTOML_T004U_BAD", std::ffi::OsStr::from_bytes(b"\xff"));
+        let err = Converter::new()
+            .prefix("ENVTOTOML_T004U_")
+            .convert()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::NotUnicode {
+                var: "ENVTOTOML_T004U_BAD".to_string()
+            }
+        );
+    }
+
     fn write_to_file(filename: &str, content: &str) -> Result<(), std::io::Error> {
         let mut file = File::create(filename)?;
         file.write_all(content.as_bytes())?;
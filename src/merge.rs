@@ -0,0 +1,282 @@
+use toml_edit::{ArrayOfTables, DocumentMut, Item, TableLike};
+
+use crate::converter::{ArrayMerge, TableMerge, TableStyle};
+use crate::table::{Node, Table};
+use crate::{Converter, Error};
+
+/// Applies the converted variables in `root` on top of `document`, keeping the
+/// comments, ordering and formatting of everything they do not touch.
+pub(crate) fn merge(converter: &Converter, document: &str, root: &Table) -> Result<String, Error> {
+    let mut doc: DocumentMut =
+        document
+            .parse()
+            .map_err(|err: toml_edit::TomlError| Error::InvalidToml {
+                reason: err.message().to_string(),
+            })?;
+    merge_table(converter, doc.as_table_mut(), root, &mut Vec::new(), false);
+    Ok(doc.to_string())
+}
+
+/// Merges `table` into `target`. `inline` is set within inline tables, where only
+/// values can be inserted.
+fn merge_table(
+    converter: &Converter,
+    target: &mut dyn TableLike,
+    table: &Table,
+    path: &mut Vec<String>,
+    inline: bool,
+) {
+    for (key, node) in table.sorted_entries(path, converter) {
+        path.push(key.clone());
+        match target.get_mut(key) {
+            Some(item) => {
+                let was_table = item.is_table() || item.is_array_of_tables();
+                merge_item(converter, item, node, path);
+                // The key of a `[header]` has no spacing for `key = value`.
+                if was_table && item.is_value() {
+                    if let Some(mut key) = target.key_mut(key) {
+                        key.leaf_decor_mut().clear();
+                    }
+                }
+            }
+            None => {
+                target.insert(key, new_item(converter, node, path, inline));
+            }
+        }
+        path.pop();
+    }
+}
+
+fn merge_item(converter: &Converter, item: &mut Item, node: &Node, path: &mut Vec<String>) {
+    match node {
+        Node::Table(table) if converter.table_merge == TableMerge::Deep && item.is_table_like() => {
+            let inline = item.is_inline_table();
+            if let Some(target) = item.as_table_like_mut() {
+                merge_table(converter, target, table, path, inline);
+            }
+        }
+        Node::Array(elements) if converter.array_merge == ArrayMerge::Append => match item {
+            Item::Value(toml_edit::Value::Array(array)) => {
+                for (index, element) in elements.iter().enumerate() {
+                    path.push((array.len() + index).to_string());
+                    array.push(inline_value(converter, element, path));
+                    path.pop();
+                }
+            }
+            Item::ArrayOfTables(array) if node.is_array_of_tables() => {
+                for (index, element) in elements.iter().enumerate() {
+                    path.push((array.len() + index).to_string());
+                    if let Item::Table(table) = new_item(converter, element, path, false) {
+                        array.push(table);
+                    }
+                    path.pop();
+                }
+            }
+            _ => replace(item, new_item(converter, node, path, item.is_value())),
+        },
+        Node::Array(elements)
+            if converter.table_merge == TableMerge::Deep && node.is_array_of_tables() =>
+        {
+            match item {
+                Item::ArrayOfTables(array) => {
+                    for (index, element) in elements.iter().enumerate() {
+                        path.push(index.to_string());
+                        match (array.get_mut(index), element) {
+                            (Some(target), Node::Table(table)) => {
+                                merge_table(converter, target, table, path, false);
+                            }
+                            _ => {
+                                if let Item::Table(table) =
+                                    new_item(converter, element, path, false)
+                                {
+                                    array.push(table);
+                                }
+                            }
+                        }
+                        path.pop();
+                    }
+                }
+                Item::Value(toml_edit::Value::Array(array)) => {
+                    for (index, element) in elements.iter().enumerate() {
+                        path.push(index.to_string());
+                        match (array.get_mut(index), element) {
+                            (Some(toml_edit::Value::InlineTable(target)), Node::Table(table)) => {
+                                merge_table(converter, target, table, path, true);
+                            }
+                            (Some(value), _) => {
+                                let decor = value.decor().clone();
+                                *value = inline_value(converter, element, path);
+                                *value.decor_mut() = decor;
+                            }
+                            (None, _) => array.push(inline_value(converter, element, path)),
+                        }
+                        path.pop();
+                    }
+                }
+                _ => replace(item, new_item(converter, node, path, item.is_value())),
+            }
+        }
+        _ => replace(item, new_item(converter, node, path, item.is_value())),
+    }
+}
+
+/// Replaces `item`, keeping the comments and whitespace around it when the new
+/// item has the same kind.
+fn replace(item: &mut Item, mut new: Item) {
+    match (&*item, &mut new) {
+        (Item::Value(old), Item::Value(value)) => *value.decor_mut() = old.decor().clone(),
+        (Item::Table(old), Item::Table(table)) => {
+            *table.decor_mut() = old.decor().clone();
+            if let Some(position) = old.position() {
+                table.set_position(position);
+            }
+        }
+        _ => {}
+    }
+    *item = new;
+}
+
+/// Builds an item for a key that the document does not have yet, honoring the
+/// converter's table styles.
+fn new_item(converter: &Converter, node: &Node, path: &mut Vec<String>, inline: bool) -> Item {
+    let style = converter.table_style_at(path.len());
+    match node {
+        Node::Table(table) if !inline && style != TableStyle::Inline => {
+            let mut new = toml_edit::Table::new();
+            for (key, child) in table.sorted_entries(path, converter) {
+                path.push(key.clone());
+                new.insert(key, new_item(converter, child, path, false));
+                path.pop();
+            }
+            new.set_dotted(style == TableStyle::Dotted);
+            new.set_implicit(!table.has_body(path.len(), converter) && !new.is_empty());
+            Item::Table(new)
+        }
+        Node::Array(elements)
+            if !inline && style == TableStyle::Header && node.is_array_of_tables() =>
+        {
+            let mut array = ArrayOfTables::new();
+            for (index, element) in elements.iter().enumerate() {
+                path.push(index.to_string());
+                if let Item::Table(table) = new_item(converter, element, path, false) {
+                    array.push(table);
+                }
+                path.pop();
+            }
+            Item::ArrayOfTables(array)
+        }
+        _ => Item::Value(inline_value(converter, node, path)),
+    }
+}
+
+fn inline_value(converter: &Converter, node: &Node, path: &mut Vec<String>) -> toml_edit::Value {
+    node.format_inline(path, converter)
+        .parse()
+        .expect("generated values are valid TOML")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ArrayMerge, Converter, TableMerge};
+
+    const DOCUMENT: &str = "\
+# Service defaults
+name = \"svc\" # the service name
+ports = [80]
+
+[db]
+# Primary database
+host = \"localhost\"
+pool = 5
+
+[[servers]]
+host = \"a\"
+";
+
+    #[test]
+    fn test_merge_deep() {
+        let converter = Converter::new().prefix("APP_");
+        let merged = converter
+            .merge_vars(
+                DOCUMENT,
+                [
+                    ("APP_DB__HOST", "db.internal"),
+                    ("APP_PORTS__0", "8080"),
+                    ("APP_LOG__LEVEL", "debug"),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            merged,
+            "\
+# Service defaults
+name = \"svc\" # the service name
+ports = [8080]
+
+[db]
+# Primary database
+host = \"db.internal\"
+pool = 5
+
+[[servers]]
+host = \"a\"
+
+[log]
+level = \"debug\"
+"
+        );
+    }
+
+    #[test]
+    fn test_merge_replace_and_append() {
+        let converter = Converter::new()
+            .prefix("APP_")
+            .table_merge(TableMerge::Replace)
+            .array_merge(ArrayMerge::Append);
+        let merged = converter
+            .merge_vars(
+                DOCUMENT,
+                [
+                    ("APP_DB__HOST", "db.internal"),
+                    ("APP_PORTS__0", "8080"),
+                    ("APP_SERVERS__0__HOST", "b"),
+                ],
+            )
+            .unwrap();
+        let parsed: toml::Table = merged.parse().unwrap();
+        assert_eq!(parsed["db"].as_table().unwrap().len(), 1);
+        assert_eq!(parsed["ports"].as_array().unwrap().len(), 2);
+        assert_eq!(parsed["servers"].as_array().unwrap().len(), 2);
+        assert!(merged.starts_with(
+            "# Service defaults\nname = \"svc\" # the service name\nports = [80, 8080]\n"
+        ));
+
+        assert!(converter.merge_vars("a = ", [("APP_X", "1")]).is_err());
+    }
+
+    #[test]
+    fn test_merge_array_elements() {
+        let converter = Converter::new().prefix("APP_");
+        assert_eq!(
+            converter
+                .merge_vars("[[a]]\nb = 1\nc = 5\n", [("APP_A__0__B", "2")])
+                .unwrap(),
+            "[[a]]\nb = 2\nc = 5\n"
+        );
+        assert_eq!(
+            converter
+                .merge_vars(
+                    "a = [{ b = 1, c = 5 }]\n",
+                    [("APP_A__0__B", "2"), ("APP_A__1__B", "3")]
+                )
+                .unwrap(),
+            "a = [{ b = 2, c = 5 }, { b = 3 }]\n"
+        );
+        assert_eq!(
+            converter
+                .merge_vars("[a]\nb = 1\n", [("APP_A", "2")])
+                .unwrap(),
+            "a = 2\n"
+        );
+    }
+}
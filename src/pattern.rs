@@ -0,0 +1,63 @@
+/// Matches `text` against a pattern where `*` stands for any run of characters.
+///
+/// Matching ignores ASCII case, so `APP_*_ORIGINS` and `*password*` match variable
+/// names and lowercased keys alike.
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let text: Vec<char> = text.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+    // Greedy matching that backtracks to the most recent `*`.
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Removes `suffix` from the end of `text`, ignoring ASCII case like
+/// [`wildcard_match`].
+pub(crate) fn strip_suffix_ignore_case<'a>(text: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = text.len().checked_sub(suffix.len())?;
+    let (rest, end) = (text.get(..split)?, text.get(split..)?);
+    end.eq_ignore_ascii_case(suffix).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("APP_ALLOWED_ORIGINS", "APP_ALLOWED_ORIGINS"));
+        assert!(wildcard_match("APP_*_ORIGINS", "app_cors_allowed_origins"));
+        assert!(wildcard_match("*password*", "db.PASSWORD_hash"));
+        assert!(wildcard_match("*_key", "api_key"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("*_key", "api_keys"));
+        assert!(!wildcard_match("APP_*", "OTHER_X"));
+    }
+
+    #[test]
+    fn test_strip_suffix_ignore_case() {
+        assert_eq!(strip_suffix_ignore_case("L__list", "__LIST"), Some("L"));
+        assert_eq!(
+            strip_suffix_ignore_case("HOSTS__LIST", "__LIST"),
+            Some("HOSTS")
+        );
+        assert_eq!(strip_suffix_ignore_case("LIST", "__LIST"), None);
+        assert_eq!(strip_suffix_ignore_case("é_LIST", "__LIST"), None);
+    }
+}
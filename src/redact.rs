@@ -0,0 +1,164 @@
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+use crate::pattern::wildcard_match;
+use crate::{reverse, Converter, Error};
+
+/// How [`Converter::redact`] masks secret values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redaction {
+    /// Replace each secret with the given string.
+    Placeholder(String),
+    /// Replace each secret with a short fingerprint of its value, such as
+    /// `[REDACTED:3b1f0c2a]`, so that changed secrets can be told apart in logs.
+    ///
+    /// The fingerprint is not a cryptographic hash and does not protect weak
+    /// secrets from guessing.
+    Fingerprint,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Redaction::Placeholder("[REDACTED]".to_string())
+    }
+}
+
+impl Redaction {
+    fn mask(&self, raw: &str) -> String {
+        match self {
+            Redaction::Placeholder(placeholder) => placeholder.clone(),
+            Redaction::Fingerprint => format!("[REDACTED:{:08x}]", fingerprint(raw)),
+        }
+    }
+}
+
+/// FNV-1a, folded to 32 bits.
+fn fingerprint(raw: &str) -> u32 {
+    let hash = raw.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash ^ (hash >> 32)) as u32
+}
+
+/// Masks the secret values of `document`, keeping its comments and formatting.
+pub(crate) fn redact(converter: &Converter, document: &str) -> Result<String, Error> {
+    let mut doc: DocumentMut =
+        document
+            .parse()
+            .map_err(|err: toml_edit::TomlError| Error::InvalidToml {
+                reason: err.message().to_string(),
+            })?;
+    redact_table(converter, doc.as_table_mut(), &mut Vec::new(), false);
+    Ok(doc.to_string())
+}
+
+/// Whether the key at the end of `path` names a secret, or the variable for the
+/// path is marked as one.
+fn is_secret(converter: &Converter, path: &[String]) -> bool {
+    let Some(key) = path.last() else {
+        return false;
+    };
+    let segment = converter.env_segment(key);
+    let var = reverse::var_name(converter, path);
+    converter
+        .secret_keys
+        .iter()
+        .any(|pattern| wildcard_match(pattern, key) || wildcard_match(pattern, &segment))
+        || converter
+            .secret_vars
+            .iter()
+            .any(|pattern| wildcard_match(pattern, &var))
+}
+
+fn redact_table(
+    converter: &Converter,
+    table: &mut dyn TableLike,
+    path: &mut Vec<String>,
+    secret: bool,
+) {
+    for (key, item) in table.iter_mut() {
+        path.push(key.get().to_string());
+        let secret = secret || is_secret(converter, path);
+        redact_item(converter, item, path, secret);
+        path.pop();
+    }
+}
+
+fn redact_item(converter: &Converter, item: &mut Item, path: &mut Vec<String>, secret: bool) {
+    match item {
+        Item::Value(value) => redact_value(converter, value, path, secret),
+        Item::Table(table) => redact_table(converter, table, path, secret),
+        Item::ArrayOfTables(array) => {
+            for (index, table) in array.iter_mut().enumerate() {
+                path.push(index.to_string());
+                redact_table(converter, table, path, secret);
+                path.pop();
+            }
+        }
+        Item::None => {}
+    }
+}
+
+/// Masks the scalars within `value` if it is a secret, or if it is an array or
+/// inline table holding secret keys.
+fn redact_value(converter: &Converter, value: &mut Value, path: &mut Vec<String>, secret: bool) {
+    match value {
+        Value::Array(array) => {
+            for (index, element) in array.iter_mut().enumerate() {
+                path.push(index.to_string());
+                redact_value(converter, element, path, secret);
+                path.pop();
+            }
+        }
+        Value::InlineTable(table) => redact_table(converter, table, path, secret),
+        scalar if secret => {
+            let raw = match &*scalar {
+                Value::String(raw) => raw.value().clone(),
+                other => other.clone().decorated("", "").to_string(),
+            };
+            let decor = scalar.decor().clone();
+            *scalar = Value::from(converter.redaction.mask(&raw));
+            *scalar.decor_mut() = decor;
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let converter = Converter::new().prefix("APP_").secret("APP_DB__URL");
+        let document = "name = \"svc\"\napi_key = \"abc\" # rotated monthly\n\n\
+                        [db]\nurl = \"postgres://u:p@h\"\npassword = 1234\n\n\
+                        [[tokens]]\nvalue = \"t1\"\n\n[auth]\nsecrets = { a = \"x\", b = [1, 2] }\n";
+        let redacted = converter.redact(document).unwrap();
+        assert_eq!(
+            redacted,
+            "name = \"svc\"\napi_key = \"[REDACTED]\" # rotated monthly\n\n\
+             [db]\nurl = \"[REDACTED]\"\npassword = \"[REDACTED]\"\n\n\
+             [[tokens]]\nvalue = \"[REDACTED]\"\n\n\
+             [auth]\nsecrets = { a = \"[REDACTED]\", b = [\"[REDACTED]\", \"[REDACTED]\"] }\n"
+        );
+        assert!(redacted.parse::<toml::Table>().is_ok());
+
+        let fingerprinted = Converter::new()
+            .redaction(Redaction::Fingerprint)
+            .secret_keys(["*pass*"])
+            .redact("db_pass = \"a\"\nother_pass = \"a\"\napi_key = \"b\"\n")
+            .unwrap();
+        let fingerprint = format!("\"[REDACTED:{:08x}]\"", fingerprint("a"));
+        assert_eq!(
+            fingerprinted,
+            format!(
+                "db_pass = {0}\nother_pass = {0}\napi_key = \"b\"\n",
+                fingerprint
+            )
+        );
+        assert!(matches!(
+            converter.redact("a = "),
+            Err(Error::InvalidToml { .. })
+        ));
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashSet;
+
+use crate::pattern::wildcard_match;
+use crate::value::Value;
+use crate::{Converter, Error};
+
+/// Output formats for variables produced by [`Converter::to_env`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvFormat {
+    /// `KEY=value` lines that can be read back as a `.env` file.
+    Dotenv,
+    /// `export KEY='value'` lines for POSIX shells.
+    Shell,
+    /// A JSON object mapping each variable to its value.
+    Json,
+}
+
+impl EnvFormat {
+    /// Formats `vars` in this format, one variable per line for `.env` and shell
+    /// output, keeping the given order.
+    pub fn format(self, vars: &[(String, String)]) -> String {
+        match self {
+            EnvFormat::Dotenv => vars
+                .iter()
+                .map(|(var, value)| format!("{}={}\n", var, dotenv_value(value)))
+                .collect(),
+            EnvFormat::Shell => vars
+                .iter()
+                .map(|(var, value)| format!("export {}='{}'\n", var, value.replace('\'', r"'\''")))
+                .collect(),
+            EnvFormat::Json => {
+                let map: serde_json::Map<String, serde_json::Value> = vars
+                    .iter()
+                    .map(|(var, value)| (var.clone(), serde_json::Value::String(value.clone())))
+                    .collect();
+                let mut json = serde_json::to_string_pretty(&map).unwrap_or_default();
+                json.push('\n');
+                json
+            }
+        }
+    }
+}
+
+/// Quotes a `.env` value so that [`Dotenv`](crate::Dotenv) and dotenvy read it
+/// back unchanged, without substituting `$` references.
+fn dotenv_value(value: &str) -> String {
+    let is_bare = |c: char| c.is_ascii_alphanumeric() || "-_./:,@+%=~".contains(c);
+    if value.chars().all(is_bare) {
+        value.to_string()
+    } else if !value.contains(['\'', '\n', '\r']) {
+        format!("'{}'", value)
+    } else {
+        let mut quoted = String::from("\"");
+        for c in value.chars() {
+            match c {
+                '\\' | '"' | '$' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                '\n' => quoted.push_str("\\n"),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+/// Flattens a TOML document into `(name, value)` variables using the naming rules
+/// of `converter`, in document order.
+pub(crate) fn to_env(converter: &Converter, toml: &str) -> Result<Vec<(String, String)>, Error> {
+    let table: toml::Table = toml
+        .parse()
+        .map_err(|err: toml::de::Error| Error::InvalidToml {
+            reason: err.message().to_string(),
+        })?;
+    let mut vars = Vec::new();
+    let mut names = HashSet::new();
+    let mut path = Vec::new();
+    for (key, value) in &table {
+        path.push(key.clone());
+        flatten(converter, value, &mut path, &mut vars, &mut names)?;
+        path.pop();
+    }
+    Ok(vars)
+}
+
+fn flatten(
+    converter: &Converter,
+    value: &toml::Value,
+    path: &mut Vec<String>,
+    vars: &mut Vec<(String, String)>,
+    names: &mut HashSet<String>,
+) -> Result<(), Error> {
+    let var = checked_var_name(converter, path)?;
+    if converter
+        .json_vars
+        .iter()
+        .any(|pattern| wildcard_match(pattern, &var))
+        && matches!(value, toml::Value::Table(_) | toml::Value::Array(_))
+    {
+        let json = to_json(value).map_err(|reason| Error::InvalidJson {
+            var: var.clone(),
+            reason,
+        })?;
+        return emit(vars, names, var, json.to_string());
+    }
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                path.push(key.clone());
+                flatten(converter, value, path, vars, names)?;
+                path.pop();
+            }
+        }
+        toml::Value::Array(elements) => {
+            let scalars: Option<Vec<String>> = elements.iter().map(scalar).collect();
+            let list = match (converter.list_delimiter_for(&var), &converter.list_suffix) {
+                (Some(delimiter), _) => Some((var, delimiter)),
+                (None, Some((suffix, delimiter))) => Some((var + suffix, *delimiter)),
+                (None, None) => None,
+            };
+            match (list, scalars) {
+                (Some((var, delimiter)), Some(scalars)) => {
+                    emit(vars, names, var, delimiter.join(&scalars))?;
+                }
+                _ => {
+                    for (index, element) in elements.iter().enumerate() {
+                        path.push(index.to_string());
+                        flatten(converter, element, path, vars, names)?;
+                        path.pop();
+                    }
+                }
+            }
+        }
+        scalar_value => emit(vars, names, var, scalar(scalar_value).unwrap_or_default())?,
+    }
+    Ok(())
+}
+
+/// Adds a variable to the output, failing if another key already gave its name.
+fn emit(
+    vars: &mut Vec<(String, String)>,
+    names: &mut HashSet<String>,
+    var: String,
+    value: String,
+) -> Result<(), Error> {
+    if !names.insert(var.clone()) {
+        return Err(Error::InvalidKey {
+            var,
+            reason: "another key gives the same variable name".to_string(),
+        });
+    }
+    vars.push((var, value));
+    Ok(())
+}
+
+/// Builds the variable name for a key path, choosing the prefix whose root table
+/// the path lies in.
+pub(crate) fn var_name(converter: &Converter, path: &[String]) -> String {
+    let (prefix, keys) = split_prefix(converter, path);
+    let segments: Vec<String> = keys.iter().map(|key| converter.env_segment(key)).collect();
+    format!("{}{}", prefix, segments.join(&converter.separator))
+}
+
+/// Builds the variable name for a key path like [`var_name`], failing if a key
+/// gives a segment that is not a valid variable name or that would not be read
+/// back as that same key.
+fn checked_var_name(converter: &Converter, path: &[String]) -> Result<String, Error> {
+    let var = var_name(converter, path);
+    let (_, keys) = split_prefix(converter, path);
+    let is_name = |segment: &str| {
+        segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    for key in keys {
+        let segment = converter.env_segment(key);
+        let read_back = converter.convert_segment(&segment);
+        let reason = if segment.is_empty() || !is_name(&segment) {
+            "a key contains characters other than letters, digits and `_`, \
+             which a key case such as kebab or snake can map to `_`"
+                .to_string()
+        } else if !converter.separator.is_empty() && segment.contains(&converter.separator) {
+            "a key contains the separator, so the variable would be read back as nested keys"
+                .to_string()
+        } else if read_back != *key {
+            format!("the key `{}` would be read back as `{}`", key, read_back)
+        } else {
+            continue;
+        };
+        return Err(Error::InvalidKey { var, reason });
+    }
+    Ok(var)
+}
+
+/// Returns the prefix for a key path and the keys after the table it maps to.
+fn split_prefix<'a, 'p>(converter: &'a Converter, path: &'p [String]) -> (&'a str, &'p [String]) {
+    let mapped = converter
+        .prefix_tables
+        .iter()
+        .map(|(prefix, table)| (prefix, table.split('.').collect::<Vec<_>>()))
+        .filter(|(_, table)| {
+            path.len() > table.len() && path.iter().zip(table).all(|(a, b)| a == b)
+        })
+        .max_by_key(|(_, table)| table.len());
+    let (prefix, rest) = match mapped {
+        Some((prefix, table)) => (prefix.as_str(), &path[table.len()..]),
+        None => {
+            let kept = converter
+                .match_prefix(&converter.prefix)
+                .map(|(root, _)| root)
+                .unwrap_or_default();
+            let rest = path.strip_prefix(kept.as_slice()).unwrap_or(path);
+            (converter.prefix.as_str(), rest)
+        }
+    };
+    (prefix, rest)
+}
+
+/// Formats a scalar as the raw variable value, or returns `None` for tables and arrays.
+pub(crate) fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(Value::Float(*value).to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        toml::Value::Datetime(value) => Some(value.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => None,
+    }
+}
+
+fn to_json(value: &toml::Value) -> Result<serde_json::Value, String> {
+    Ok(match value {
+        toml::Value::String(value) => serde_json::Value::String(value.clone()),
+        toml::Value::Integer(value) => serde_json::Value::from(*value),
+        toml::Value::Float(value) => serde_json::Number::from_f64(*value)
+            .map(serde_json::Value::Number)
+            .ok_or_else(|| format!("{} cannot be represented in JSON", Value::Float(*value)))?,
+        toml::Value::Boolean(value) => serde_json::Value::Bool(*value),
+        toml::Value::Datetime(value) => serde_json::Value::String(value.to_string()),
+        toml::Value::Array(elements) => {
+            serde_json::Value::Array(elements.iter().map(to_json).collect::<Result<_, _>>()?)
+        }
+        toml::Value::Table(table) => serde_json::Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), to_json(value)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Dotenv, KeyCase, ListDelimiter, VarSource};
+
+    #[test]
+    fn test_env_formats() {
+        let vars = vec![
+            ("APP_A".to_string(), "plain-value".to_string()),
+            ("APP_B".to_string(), "two words $HOME".to_string()),
+            ("APP_C".to_string(), "it's\n\"quoted\" $x \\".to_string()),
+            ("APP_D".to_string(), String::new()),
+        ];
+        let dotenv = EnvFormat::Dotenv.format(&vars);
+        assert_eq!(
+            dotenv,
+            "APP_A=plain-value\nAPP_B='two words $HOME'\nAPP_C=\"it's\\n\\\"quoted\\\" \\$x \\\\\"\nAPP_D=\n"
+        );
+        let parsed: Vec<(String, String)> = dotenvy::from_read_iter(dotenv.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(parsed, vars);
+        let read: Vec<(String, String)> = Dotenv::new()
+            .contents(dotenv)
+            .vars()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, vars);
+
+        assert_eq!(
+            EnvFormat::Shell.format(&vars[..2]),
+            "export APP_A='plain-value'\nexport APP_B='two words $HOME'\n"
+        );
+        assert_eq!(
+            EnvFormat::Json.format(&vars[..1]),
+            "{\n  \"APP_A\": \"plain-value\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let converter = Converter::new()
+            .prefix("APP_")
+            .key_case(KeyCase::Camel)
+            .rename("DB", "database")
+            .list("APP_TAGS", ListDelimiter::Comma)
+            .json("APP_FEATURES");
+        let dotenv = Dotenv::new().contents(
+            "APP_NAME=svc\n\
+             APP_DB__MAX_POOL=5\n\
+             APP_TAGS='a,b\\,c'\n\
+             APP_FEATURES='{\"on\":true}'\n\
+             APP_HOSTS__0__HOST_NAME=a\n\
+             APP_HOSTS__1__HOST_NAME=b\n\
+             APP_RATIO=0.5\n",
+        );
+        let toml = converter.convert_source(&dotenv).unwrap();
+        let vars = converter.to_env(&toml).unwrap();
+        assert_eq!(
+            vars.iter()
+                .map(|(var, value)| format!("{}={}", var, value))
+                .collect::<Vec<_>>(),
+            [
+                "APP_NAME=svc",
+                "APP_RATIO=0.5",
+                "APP_TAGS=a,b\\,c",
+                "APP_DB__MAX_POOL=5",
+                "APP_FEATURES={\"on\":true}",
+                "APP_HOSTS__0__HOST_NAME=a",
+                "APP_HOSTS__1__HOST_NAME=b",
+            ]
+        );
+        assert_eq!(converter.convert_vars(vars).unwrap(), toml);
+
+        let kept = Converter::new()
+            .prefix("APP_")
+            .keep_prefix(true)
+            .prefix_table("CACHE_", "services.cache");
+        let vars = kept
+            .to_env("[app]\nport = 80\n[services.cache]\nttl = 5\n")
+            .unwrap();
+        assert_eq!(
+            vars,
+            [
+                ("APP_PORT".to_string(), "80".to_string()),
+                ("CACHE_TTL".to_string(), "5".to_string()),
+            ]
+        );
+        assert!(matches!(
+            kept.to_env("a = "),
+            Err(Error::InvalidToml { .. })
+        ));
+
+        let plain = Converter::new().prefix("APP_");
+        for toml in [
+            "max-conn = 1\n",
+            "\"a b\" = 3\n",
+            "[a__b]\nc = 2\n",
+            "\"\" = 1\n",
+        ] {
+            let err = plain.to_env(toml).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidKey { .. }),
+                "{}: {:?}",
+                toml,
+                err
+            );
+        }
+        assert_eq!(
+            plain.to_env("[a__b]\nc = 2\n").unwrap_err().var(),
+            Some("APP_A__B")
+        );
+
+        // Keys that would not read back as themselves, or that give the same name.
+        let err = plain.to_env("Port = 1\nport = 2\n").unwrap_err();
+        assert!(matches!(err, Error::InvalidKey { .. }));
+        assert_eq!(err.var(), Some("APP_PORT"));
+        let camel = Converter::new().prefix("APP_").key_case(KeyCase::Camel);
+        for toml in ["maxConn = 1\nmax_conn = 2\n", "max_conn = 1\nmaxConn = 2\n"] {
+            let err = camel.to_env(toml).unwrap_err();
+            assert!(matches!(err, Error::InvalidKey { .. }), "{}", toml);
+            assert_eq!(err.var(), Some("APP_MAX_CONN"));
+        }
+        let err = Converter::new()
+            .prefix_table("CACHE_", "services.cache")
+            .to_env("cache_ttl = 1\n[services.cache]\nttl = 5\n")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "environment variable `CACHE_TTL` has an invalid key: another key gives the same variable name"
+        );
+
+        let kebab = Converter::new().prefix("APP_").key_case(KeyCase::Kebab);
+        let vars = kebab.to_env("max-conn = 1\n").unwrap();
+        assert_eq!(vars, [("APP_MAX_CONN".to_string(), "1".to_string())]);
+        assert_eq!(kebab.convert_vars(vars).unwrap(), "max-conn = 1\n");
+    }
+}
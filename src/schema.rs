@@ -0,0 +1,845 @@
+use std::fmt;
+
+use regex::Regex;
+
+use crate::converter::ListDelimiter;
+use crate::table::{Leaf, Node, Table};
+use crate::value::Value;
+use crate::{reverse, template, Converter, Error};
+
+/// The expected shape of the generated configuration: types, required keys,
+/// ranges, allowed values, patterns and defaults, keyed by dotted TOML path.
+///
+/// Fields of arrays of tables apply to every element, so `servers.port` checks
+/// the `port` of each `[[servers]]` table.
+///
+/// ```
+/// use envmtotoml::{Converter, Field, FieldType, Schema};
+///
+/// let schema = Schema::new()
+///     .field("port", Field::new().kind(FieldType::Integer).required().min(1.0).max(65535.0))
+///     .field("log.level", Field::new().one_of(["debug", "info"]).default_value("info"));
+/// let toml = Converter::new()
+///     .prefix("APP_")
+///     .schema(schema)
+///     .convert_vars([("APP_PORT", "8080")])
+///     .unwrap();
+/// assert_eq!(toml, "port = 8080\n\n[log]\nlevel = \"info\"\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    fields: Vec<(Vec<String>, Field)>,
+}
+
+/// The rules for a single key of a [`Schema`].
+#[derive(Debug, Clone, Default)]
+pub struct Field {
+    kind: Option<FieldType>,
+    items: Option<FieldType>,
+    required: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+    allowed: Vec<String>,
+    /// The compiled pattern, or why it does not compile.
+    pattern: Option<Result<Regex, String>>,
+    default: Option<String>,
+}
+
+/// The TOML type of a [`Field`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Datetime,
+    Array,
+    Table,
+}
+
+/// A single schema violation, found while converting with a [`Schema`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The variable that set the key, or that would set a missing key.
+    pub var: String,
+    /// The dotted TOML path of the key.
+    pub key: String,
+    /// What is wrong with the value, such as `must be at most 65535`.
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "environment variable `{}` {}", self.var, self.message)
+    }
+}
+
+/// Keys of a TOML schema table that define rules rather than nested fields.
+const RULES: [&str; 8] = [
+    "type", "items", "required", "min", "max", "enum", "pattern", "default",
+];
+
+impl Schema {
+    /// Creates an empty schema.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the rules for the key at the dotted `path`, such as `db.port`.
+    pub fn field(mut self, path: &str, field: Field) -> Self {
+        self.fields
+            .push((path.split('.').map(str::to_string).collect(), field));
+        self
+    }
+
+    /// Loads a schema from a TOML document whose tables hold the rules of each key:
+    ///
+    /// ```toml
+    /// [port]
+    /// type = "integer"
+    /// required = true
+    /// min = 1
+    /// max = 65535
+    ///
+    /// [log.level]
+    /// enum = ["debug", "info"]
+    /// default = "info"
+    /// ```
+    ///
+    /// A table is a field if it has a rule (`type`, `items`, `required`, `min`,
+    /// `max`, `enum`, `pattern` or `default`) that is not itself a table.
+    pub fn from_toml(schema: &str) -> Result<Self, Error> {
+        let root: toml::Table = schema
+            .parse()
+            .map_err(|err: toml::de::Error| invalid(err.message()))?;
+        let mut loaded = Self::new();
+        loaded.load_toml(&root, &mut Vec::new())?;
+        Ok(loaded)
+    }
+
+    fn load_toml(&mut self, table: &toml::Table, path: &mut Vec<String>) -> Result<(), Error> {
+        for (key, value) in table {
+            path.push(key.clone());
+            let toml::Value::Table(rules) = value else {
+                return Err(invalid(format!(
+                    "`{}` must be a table of rules",
+                    path.join(".")
+                )));
+            };
+            let is_field = rules
+                .iter()
+                .any(|(rule, value)| RULES.contains(&rule.as_str()) && !value.is_table());
+            if is_field {
+                let field = Field::from_toml(rules, &path.join("."))?;
+                self.fields.push((path.clone(), field));
+            } else {
+                self.load_toml(rules, path)?;
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+
+    /// Loads a schema from a JSON Schema document, reading `properties`,
+    /// `required`, `type`, `format`, `items`, `minimum`, `maximum`, `enum`,
+    /// `pattern` and `default`. Other keywords are ignored.
+    pub fn from_json_schema(schema: &str) -> Result<Self, Error> {
+        let root: serde_json::Value =
+            serde_json::from_str(schema).map_err(|err| invalid(err.to_string()))?;
+        let mut loaded = Self::new();
+        loaded.load_json(&root, &mut Vec::new(), false)?;
+        Ok(loaded)
+    }
+
+    fn load_json(
+        &mut self,
+        schema: &serde_json::Value,
+        path: &mut Vec<String>,
+        required: bool,
+    ) -> Result<(), Error> {
+        let name = || path.join(".");
+        if let Some(properties) = schema.get("properties") {
+            let properties = properties.as_object().ok_or_else(|| {
+                invalid(format!("`properties` of `{}` must be an object", name()))
+            })?;
+            let required_keys: Vec<&str> = schema
+                .get("required")
+                .and_then(serde_json::Value::as_array)
+                .map(|keys| keys.iter().filter_map(serde_json::Value::as_str).collect())
+                .unwrap_or_default();
+            // A required object is checked through its own required keys, if it has any.
+            if required && required_keys.is_empty() && !path.is_empty() {
+                let field = Field::new().kind(FieldType::Table).required();
+                self.fields.push((path.clone(), field));
+            }
+            for (key, property) in properties {
+                path.push(key.clone());
+                self.load_json(property, path, required_keys.contains(&key.as_str()))?;
+                path.pop();
+            }
+            return Ok(());
+        }
+        if path.is_empty() {
+            return Ok(());
+        }
+
+        let mut field = Field::new();
+        field.required = required;
+        field.kind = json_type(schema)?;
+        field.items = match schema.get("items") {
+            Some(items) => json_type(items)?,
+            None => None,
+        };
+        let number = |keyword: &str| match schema.get(keyword) {
+            Some(value) => value
+                .as_f64()
+                .map(Some)
+                .ok_or_else(|| invalid(format!("`{}` of `{}` must be a number", keyword, name()))),
+            None => Ok(None),
+        };
+        field.min = number("minimum")?;
+        field.max = number("maximum")?;
+        if let Some(values) = schema.get("enum") {
+            let values = values
+                .as_array()
+                .ok_or_else(|| invalid(format!("`enum` of `{}` must be an array", name())))?;
+            field.allowed = values
+                .iter()
+                .map(|value| json_raw(value).ok_or_else(|| invalid_value("enum", &name())))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(pattern) = schema.get("pattern") {
+            let pattern = pattern
+                .as_str()
+                .ok_or_else(|| invalid(format!("`pattern` of `{}` must be a string", name())))?;
+            field.pattern = Some(Ok(compile(pattern, &name())?));
+        }
+        if let Some(default) = schema.get("default") {
+            field.default =
+                Some(json_raw(default).ok_or_else(|| invalid_value("default", &name()))?);
+        }
+        self.fields.push((path.clone(), field));
+        // Fields of the tables in an array apply to each element.
+        if let Some(items) = schema
+            .get("items")
+            .filter(|items| items.get("properties").is_some())
+        {
+            self.load_json(items, path, false)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a template value of the type required at the configuration `path`,
+    /// used to coerce the variable setting it. Array indices in `path` match any
+    /// element, and an index as last segment selects the field's item type.
+    pub(crate) fn expected(&self, path: &[String]) -> Option<toml::Value> {
+        let is_index = |segment: &String| segment.bytes().all(|b| b.is_ascii_digit());
+        let fields: Vec<&String> = path.iter().filter(|segment| !is_index(segment)).collect();
+        let (_, field) = self
+            .fields
+            .iter()
+            .find(|(field_path, _)| field_path.iter().eq(fields.iter().copied()))?;
+        if path.last().is_some_and(is_index) {
+            field.items.map(FieldType::template)
+        } else {
+            field.expected()
+        }
+    }
+
+    /// Checks the converted configuration, inserting defaults for missing keys and
+    /// adding every violation to `violations`.
+    pub(crate) fn validate(
+        &self,
+        converter: &Converter,
+        root: &mut Table,
+        violations: &mut Vec<Violation>,
+    ) -> Result<(), Error> {
+        for (path, field) in &self.fields {
+            if let Some(Err(reason)) = &field.pattern {
+                return Err(invalid(format!(
+                    "invalid pattern for `{}`: {}",
+                    path.join("."),
+                    reason
+                )));
+            }
+        }
+
+        let mut defaults = Vec::new();
+        for (path, field) in &self.fields {
+            let mut found = Vec::new();
+            resolve(root, path, &mut Vec::new(), &mut found);
+            for (actual, node) in found {
+                match node {
+                    Found::Node(node) => field.check(converter, node, &actual, violations),
+                    Found::Missing => match &field.default {
+                        Some(default) => defaults.push((actual, field, default)),
+                        None if field.required => violations.push(Violation {
+                            var: reverse::var_name(converter, &actual),
+                            key: actual.join("."),
+                            message: "is required".to_string(),
+                        }),
+                        None => {}
+                    },
+                    Found::NotTable(node) => {
+                        let violation = Violation {
+                            var: node
+                                .first_var()
+                                .map(str::to_string)
+                                .unwrap_or_else(|| reverse::var_name(converter, &actual)),
+                            key: actual.join("."),
+                            message: "must be a valid table".to_string(),
+                        };
+                        if !violations.contains(&violation) {
+                            violations.push(violation);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (mut path, field, default) in defaults {
+            let var = reverse::var_name(converter, &path);
+            let mut node = match field.expected() {
+                Some(expected) => template::coerce(&expected, default, &var, usize::MAX, None)
+                    .map_err(|_| invalid_value("default", &path.join(".")))?,
+                None => Node::Leaf(Leaf {
+                    value: Value::infer(default),
+                    var,
+                    position: usize::MAX,
+                    default: false,
+                    raw: Some(default.to_string()),
+                }),
+            };
+            node.mark_default();
+            let key = path.pop().unwrap_or_default();
+            root.insert(&path, key, node, &converter.conflict_policy)?;
+        }
+        Ok(())
+    }
+}
+
+impl Field {
+    /// Creates a field without rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the value to have the TOML type `kind`. Variables are coerced to
+    /// the type like with a [`Converter::template`].
+    pub fn kind(mut self, kind: FieldType) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Sets the type of the elements of an array field.
+    pub fn items(mut self, kind: FieldType) -> Self {
+        self.items = Some(kind);
+        self
+    }
+
+    /// Requires the key to be set, unless it has a default.
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Requires a number to be at least `min`.
+    pub fn min(mut self, min: f64) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    /// Requires a number to be at most `max`.
+    pub fn max(mut self, max: f64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// Requires the value to be one of `values`, compared with the value as written
+    /// in a variable, e.g. `"info"` or `"8080"`.
+    pub fn one_of<I, S>(mut self, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed = values.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Requires the value, as written in a variable, to match the regular
+    /// expression `pattern`. If the pattern is invalid, every conversion with the
+    /// schema fails with [`Error::InvalidSchema`], whether or not the key is set.
+    pub fn pattern(mut self, pattern: impl AsRef<str>) -> Self {
+        self.pattern = Some(Regex::new(pattern.as_ref()).map_err(|err| err.to_string()));
+        self
+    }
+
+    /// Uses `value` when the key is not set, converted as if a variable held it.
+    pub fn default_value(mut self, value: impl Into<String>) -> Self {
+        self.default = Some(value.into());
+        self
+    }
+
+    fn from_toml(rules: &toml::Table, name: &str) -> Result<Self, Error> {
+        let mut field = Self::new();
+        for (rule, value) in rules {
+            let wrong = || invalid_value(rule, name);
+            match rule.as_str() {
+                "type" => field.kind = Some(FieldType::parse(value.as_str().ok_or_else(wrong)?)?),
+                "items" => field.items = Some(FieldType::parse(value.as_str().ok_or_else(wrong)?)?),
+                "required" => field.required = value.as_bool().ok_or_else(wrong)?,
+                "min" => field.min = Some(toml_number(value).ok_or_else(wrong)?),
+                "max" => field.max = Some(toml_number(value).ok_or_else(wrong)?),
+                "enum" => {
+                    field.allowed = value
+                        .as_array()
+                        .ok_or_else(wrong)?
+                        .iter()
+                        .map(|value| reverse::scalar(value).ok_or_else(wrong))
+                        .collect::<Result<_, _>>()?;
+                }
+                "pattern" => {
+                    field.pattern = Some(Ok(compile(value.as_str().ok_or_else(wrong)?, name)?))
+                }
+                "default" => field.default = Some(toml_raw(value).ok_or_else(wrong)?),
+                _ => return Err(invalid(format!("unknown rule `{}` for `{}`", rule, name))),
+            }
+        }
+        Ok(field)
+    }
+
+    /// A template value of the field's type, if it has one.
+    fn expected(&self) -> Option<toml::Value> {
+        match (self.kind?, self.items) {
+            (FieldType::Array, Some(items)) => Some(toml::Value::Array(vec![items.template()])),
+            (kind, _) => Some(kind.template()),
+        }
+    }
+
+    fn check(
+        &self,
+        converter: &Converter,
+        node: &Node,
+        path: &[String],
+        violations: &mut Vec<Violation>,
+    ) {
+        let var = node
+            .first_var()
+            .map(str::to_string)
+            .unwrap_or_else(|| reverse::var_name(converter, path));
+        let key = path.join(".");
+
+        // A variable that could not be coerced has been reported already.
+        let reported = violations
+            .iter()
+            .any(|reported| reported.var == var && reported.key == key);
+        let mismatch = match (self.kind, node) {
+            (None, _) => None,
+            (Some(kind), node) if !kind.matches(node) => Some(kind),
+            (Some(FieldType::Array), Node::Array(elements)) => self
+                .items
+                .filter(|items| !elements.iter().all(|element| items.matches(element))),
+            _ => None,
+        };
+        if let Some(kind) = mismatch {
+            if !reported {
+                violations.push(Violation {
+                    var,
+                    key,
+                    message: format!("must be a valid {}", kind.name()),
+                });
+            }
+            return;
+        }
+        let mut violation = |message: String| {
+            violations.push(Violation {
+                var: var.clone(),
+                key: key.clone(),
+                message,
+            })
+        };
+
+        let leaves: Vec<&Leaf> = match node {
+            Node::Leaf(leaf) => vec![leaf],
+            Node::Array(elements) => elements
+                .iter()
+                .filter_map(|element| match element {
+                    Node::Leaf(leaf) => Some(leaf),
+                    _ => None,
+                })
+                .collect(),
+            Node::Table(_) => Vec::new(),
+        };
+        let pattern = self
+            .pattern
+            .as_ref()
+            .and_then(|pattern| pattern.as_ref().ok());
+        for leaf in leaves {
+            let number = match leaf.value {
+                Value::Integer(value) => Some(value as f64),
+                Value::Float(value) => Some(value),
+                _ => None,
+            };
+            if let (Some(number), Some(min)) = (number, self.min) {
+                if number < min {
+                    violation(format!("must be at least {}", min));
+                }
+            }
+            if let (Some(number), Some(max)) = (number, self.max) {
+                if number > max {
+                    violation(format!("must be at most {}", max));
+                }
+            }
+            // Enums and patterns apply to the text as written; only values without
+            // one, such as defaults from a TOML document, are rendered.
+            let raw = match (&leaf.raw, &leaf.value) {
+                (Some(raw), _) => raw.clone(),
+                (None, Value::String(value)) => value.clone(),
+                (None, value) => value.to_string(),
+            };
+            if !self.allowed.is_empty() && !self.allowed.contains(&raw) {
+                let allowed: Vec<String> = self
+                    .allowed
+                    .iter()
+                    .map(|value| format!("`{}`", value))
+                    .collect();
+                violation(format!("must be one of {}", allowed.join(", ")));
+            }
+            if let Some(pattern) = &pattern {
+                if !pattern.is_match(&raw) {
+                    violation(format!("must match the pattern `{}`", pattern.as_str()));
+                }
+            }
+        }
+    }
+}
+
+impl FieldType {
+    /// Whether `node` has this type. Integers are accepted as floats, as variables
+    /// are coerced.
+    fn matches(self, node: &Node) -> bool {
+        match (self, node) {
+            (FieldType::Table, Node::Table(_)) | (FieldType::Array, Node::Array(_)) => true,
+            (kind, Node::Leaf(leaf)) => matches!(
+                (kind, &leaf.value),
+                (FieldType::String, Value::String(_))
+                    | (FieldType::Integer, Value::Integer(_))
+                    | (FieldType::Float, Value::Float(_) | Value::Integer(_))
+                    | (FieldType::Boolean, Value::Boolean(_))
+                    | (FieldType::Datetime, Value::Datetime(_))
+            ),
+            _ => false,
+        }
+    }
+
+    fn parse(name: &str) -> Result<Self, Error> {
+        match name {
+            "string" => Ok(FieldType::String),
+            "integer" => Ok(FieldType::Integer),
+            "float" => Ok(FieldType::Float),
+            "boolean" => Ok(FieldType::Boolean),
+            "datetime" => Ok(FieldType::Datetime),
+            "array" => Ok(FieldType::Array),
+            "table" => Ok(FieldType::Table),
+            _ => Err(invalid(format!("unknown type `{}`", name))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Integer => "integer",
+            FieldType::Float => "float",
+            FieldType::Boolean => "boolean",
+            FieldType::Datetime => "datetime",
+            FieldType::Array => "array",
+            FieldType::Table => "table",
+        }
+    }
+
+    /// A value of this type, used as a template for coercion.
+    fn template(self) -> toml::Value {
+        match self {
+            FieldType::String => toml::Value::String(String::new()),
+            FieldType::Integer => toml::Value::Integer(0),
+            FieldType::Float => toml::Value::Float(0.0),
+            FieldType::Boolean => toml::Value::Boolean(false),
+            FieldType::Datetime => toml::Value::Datetime(toml::value::Datetime {
+                date: Some(toml::value::Date {
+                    year: 1979,
+                    month: 5,
+                    day: 27,
+                }),
+                time: None,
+                offset: None,
+            }),
+            FieldType::Array => toml::Value::Array(Vec::new()),
+            FieldType::Table => toml::Value::Table(toml::Table::new()),
+        }
+    }
+}
+
+/// What [`resolve`] finds at a schema path.
+enum Found<'a> {
+    Node(&'a Node),
+    Missing,
+    /// A value where the path needs a table, reported with the value's path.
+    NotTable(&'a Node),
+}
+
+/// Finds the nodes at the schema `path`, descending into every element of arrays of
+/// tables. Missing keys are reported with the path they would have.
+fn resolve<'a>(
+    table: &'a Table,
+    path: &[String],
+    actual: &mut Vec<String>,
+    found: &mut Vec<(Vec<String>, Found<'a>)>,
+) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    actual.push(first.clone());
+    match table.get(std::slice::from_ref(first)) {
+        None => {
+            let mut missing = actual.clone();
+            missing.extend_from_slice(rest);
+            found.push((missing, Found::Missing));
+        }
+        Some(node) if rest.is_empty() => found.push((actual.clone(), Found::Node(node))),
+        Some(Node::Table(table)) => resolve(table, rest, actual, found),
+        Some(Node::Array(elements)) => {
+            for (index, element) in elements.iter().enumerate() {
+                if let Node::Table(table) = element {
+                    actual.push(index.to_string());
+                    resolve(table, rest, actual, found);
+                    actual.pop();
+                }
+            }
+        }
+        Some(node @ Node::Leaf(_)) => found.push((actual.clone(), Found::NotTable(node))),
+    }
+    actual.pop();
+}
+
+fn json_type(schema: &serde_json::Value) -> Result<Option<FieldType>, Error> {
+    // A list of types such as `["string", "null"]` uses its first non-null type.
+    let name = match schema.get("type") {
+        Some(serde_json::Value::String(name)) => Some(name.as_str()),
+        Some(serde_json::Value::Array(names)) => names
+            .iter()
+            .filter_map(serde_json::Value::as_str)
+            .find(|name| *name != "null"),
+        _ => None,
+    };
+    let format = schema.get("format").and_then(serde_json::Value::as_str);
+    Ok(match (name, format) {
+        (Some("string"), Some("date-time" | "date" | "time")) => Some(FieldType::Datetime),
+        (Some("string"), _) => Some(FieldType::String),
+        (Some("integer"), _) => Some(FieldType::Integer),
+        (Some("number"), _) => Some(FieldType::Float),
+        (Some("boolean"), _) => Some(FieldType::Boolean),
+        (Some("array"), _) => Some(FieldType::Array),
+        (Some("object"), _) => Some(FieldType::Table),
+        (Some(name), _) => return Err(invalid(format!("unsupported type `{}`", name))),
+        (None, _) => None,
+    })
+}
+
+/// Writes a JSON default or enum value the way a variable would hold it.
+fn json_raw(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(value) => Some(value.clone()),
+        serde_json::Value::Number(value) => Some(value.to_string()),
+        serde_json::Value::Bool(value) => Some(value.to_string()),
+        serde_json::Value::Array(elements) => {
+            let elements: Option<Vec<String>> = elements.iter().map(json_raw).collect();
+            Some(ListDelimiter::Comma.join(&elements?))
+        }
+        serde_json::Value::Null | serde_json::Value::Object(_) => None,
+    }
+}
+
+/// Writes a TOML default value the way a variable would hold it.
+fn toml_raw(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::Array(elements) => {
+            let elements: Option<Vec<String>> = elements.iter().map(reverse::scalar).collect();
+            Some(ListDelimiter::Comma.join(&elements?))
+        }
+        value => reverse::scalar(value),
+    }
+}
+
+fn toml_number(value: &toml::Value) -> Option<f64> {
+    match value {
+        toml::Value::Integer(value) => Some(*value as f64),
+        toml::Value::Float(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn compile(pattern: &str, name: &str) -> Result<Regex, Error> {
+    Regex::new(pattern).map_err(|err| invalid(format!("invalid pattern for `{}`: {}", name, err)))
+}
+
+fn invalid(reason: impl fmt::Display) -> Error {
+    Error::InvalidSchema {
+        reason: reason.to_string(),
+    }
+}
+
+fn invalid_value(rule: &str, name: &str) -> Error {
+    invalid(format!("invalid `{}` for `{}`", rule, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML_SCHEMA: &str = r#"
+[port]
+type = "integer"
+required = true
+min = 1
+max = 65535
+
+[log.level]
+enum = ["debug", "info"]
+default = "info"
+
+[db.host]
+type = "string"
+required = true
+pattern = "^[a-z.]+$"
+
+[servers.weight]
+type = "float"
+max = 1
+"#;
+
+    const JSON_SCHEMA: &str = r#"{
+  "type": "object",
+  "required": ["port", "db"],
+  "properties": {
+    "port": {"type": "integer", "minimum": 1, "maximum": 65535},
+    "log": {"properties": {"level": {"enum": ["debug", "info"], "default": "info"}}},
+    "db": {
+      "type": "object",
+      "required": ["host"],
+      "properties": {"host": {"type": "string", "pattern": "^[a-z.]+$"}}
+    },
+    "servers": {"type": "array", "items": {"properties": {"weight": {"type": "number", "maximum": 1}}}}
+  }
+}"#;
+
+    #[test]
+    fn test_schema_violations() {
+        for schema in [
+            Schema::from_toml(TOML_SCHEMA).unwrap(),
+            Schema::from_json_schema(JSON_SCHEMA).unwrap(),
+        ] {
+            let converter = Converter::new().prefix("APP_").schema(schema);
+            let toml = converter
+                .convert_vars([("APP_PORT", "80"), ("APP_DB__HOST", "db.local")])
+                .unwrap();
+            assert_eq!(
+                toml,
+                "port = 80\n\n[db]\nhost = \"db.local\"\n\n[log]\nlevel = \"info\"\n"
+            );
+
+            let err = converter
+                .convert_vars([
+                    ("APP_PORT", "70000"),
+                    ("APP_LOG__LEVEL", "trace"),
+                    ("APP_SERVERS__0__WEIGHT", "0.5"),
+                    ("APP_SERVERS__1__WEIGHT", "abc"),
+                ])
+                .unwrap_err();
+            let Error::Validation { violations } = &err else {
+                panic!("expected violations, got {:?}", err);
+            };
+            let found: Vec<String> = violations.iter().map(Violation::to_string).collect();
+            assert_eq!(found.len(), 4, "{:?}", found);
+            for expected in [
+                "environment variable `APP_SERVERS__1__WEIGHT` must be a valid float",
+                "environment variable `APP_PORT` must be at most 65535",
+                "environment variable `APP_LOG__LEVEL` must be one of `debug`, `info`",
+                "environment variable `APP_DB__HOST` is required",
+            ] {
+                assert!(found.iter().any(|v| v == expected), "{:?}", found);
+            }
+        }
+
+        // Values that are not coerced from variables are type checked too.
+        let converter = Converter::new()
+            .prefix("APP_")
+            .schema(
+                Schema::new()
+                    .field("port", Field::new().kind(FieldType::Integer))
+                    .field(
+                        "ids",
+                        Field::new()
+                            .kind(FieldType::Array)
+                            .items(FieldType::Integer),
+                    )
+                    .field("db.host", Field::new().required())
+                    .field("db.port", Field::new()),
+            )
+            .json("APP_IDS")
+            .defaults("port = \"abc\"");
+        let err = converter
+            .convert_vars([("APP_IDS", r#"[1, "x"]"#), ("APP_DB", "x")])
+            .unwrap_err();
+        let Error::Validation { violations } = &err else {
+            panic!("expected violations, got {:?}", err);
+        };
+        let found: Vec<String> = violations.iter().map(Violation::to_string).collect();
+        assert_eq!(
+            found,
+            [
+                "environment variable `APP_PORT` must be a valid integer",
+                "environment variable `APP_IDS` must be a valid integer",
+                "environment variable `APP_DB` must be a valid table",
+            ]
+        );
+
+        let converter =
+            Converter::new().schema(Schema::new().field("name", Field::new().pattern("^[a-z]+$")));
+        let err = converter.convert_vars([("NAME", "Svc")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the configuration is invalid:\n  environment variable `NAME` must match the pattern `^[a-z]+$`"
+        );
+
+        // Enums and patterns see the variable's text, not the value written to TOML.
+        let converter = Converter::new().schema(
+            Schema::new()
+                .field("price", Field::new().pattern(r"^\d+\.\d{2}$"))
+                .field("version", Field::new().one_of(["1.10", "1.20"])),
+        );
+        assert_eq!(
+            converter
+                .convert_vars([("PRICE", "1.50"), ("VERSION", "1.10")])
+                .unwrap(),
+            "price = 1.5\nversion = 1.1\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_schemas() {
+        assert!(Schema::from_toml("[port]\ntype = \"number\"").is_err());
+        assert!(Schema::from_toml("[port]\ntype = \"integer\"\nunknown = 1").is_err());
+        assert!(Schema::from_toml("port = 1").is_err());
+        assert!(Schema::from_json_schema(r#"{"properties": {"a": {"type": "null"}}}"#).is_err());
+        assert!(Schema::from_toml("[a]\npattern = \"(\"").is_err());
+        assert!(Schema::from_json_schema(r#"{"properties": {"a": {"pattern": "("}}}"#).is_err());
+        let converter =
+            Converter::new().schema(Schema::new().field("a", Field::new().pattern("(")));
+        for vars in [vec![("A", "x")], vec![]] {
+            assert!(matches!(
+                converter.convert_vars(vars),
+                Err(Error::InvalidSchema { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,177 @@
+/// Formats `s` as a TOML string, picking the most readable form that round-trips exactly.
+///
+/// Single-line values use a literal string (`'...'`) when they contain quotes or
+/// backslashes that would otherwise need escaping, and a basic string (`"..."`) otherwise.
+/// Values spanning several lines use a multi-line literal (`'''`) or basic (`"""`) string.
+pub(crate) fn format_string(s: &str) -> String {
+    let multi_line = s.contains('\n');
+    let needs_escapes = s.contains(['"', '\\']);
+    if multi_line {
+        if s.contains('\\') && is_multi_line_literal_safe(s) {
+            format!("'''\n{}'''", s)
+        } else {
+            format_multi_line_basic(s)
+        }
+    } else if needs_escapes && is_literal_safe(s) {
+        format!("'{}'", s)
+    } else {
+        format_basic(s)
+    }
+}
+
+/// Formats a key, quoting it unless it is a valid bare key (`A-Za-z0-9_-`).
+pub(crate) fn format_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        format_basic(key)
+    }
+}
+
+/// Formats a dotted key path such as a table header, quoting each segment as needed.
+pub(crate) fn format_key_path<S: AsRef<str>>(path: &[S]) -> String {
+    path.iter()
+        .map(|segment| format_key(segment.as_ref()))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Formats `s` as a single-line basic string, escaping everything TOML requires.
+pub(crate) fn format_basic(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => push_escaped(&mut out, c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Formats `s` as a multi-line basic string, keeping its newlines unescaped.
+fn format_multi_line_basic(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 7);
+    out.push_str("\"\"\"\n");
+    let mut quote_run = 0;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // A run of three quotes, or a quote right before the closing
+            // delimiter, would end the string early.
+            '"' if quote_run == 2 || chars.peek().is_none() => {
+                out.push_str("\\\"");
+                quote_run = 0;
+                continue;
+            }
+            '"' => {
+                out.push('"');
+                quote_run += 1;
+                continue;
+            }
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push('\n'),
+            _ => push_escaped(&mut out, c),
+        }
+        quote_run = 0;
+    }
+    out.push_str("\"\"\"");
+    out
+}
+
+/// Pushes `c`, escaping it if it is a control character.
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '\u{8}' => out.push_str("\\b"),
+        '\t' => out.push_str("\\t"),
+        '\u{c}' => out.push_str("\\f"),
+        '\r' => out.push_str("\\r"),
+        c if is_control(c) => out.push_str(&format!("\\u{:04X}", c as u32)),
+        c => out.push(c),
+    }
+}
+
+/// Control characters that may not appear unescaped in any TOML string, except tab.
+fn is_control(c: char) -> bool {
+    (c < ' ' && c != '\t') || c == '\u{7f}'
+}
+
+/// Whether `s` can be written verbatim between single quotes.
+fn is_literal_safe(s: &str) -> bool {
+    !s.chars().any(|c| c == '\'' || is_control(c))
+}
+
+/// Whether `s` can be written verbatim between triple single quotes.
+fn is_multi_line_literal_safe(s: &str) -> bool {
+    !s.contains("''") && !s.ends_with('\'') && !s.chars().any(|c| c != '\n' && is_control(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Parses `value = <formatted>` with a real TOML parser and returns the string.
+    fn reparse(formatted: &str) -> String {
+        let document = format!("value = {}\n", formatted);
+        let table: toml::Table = document
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid TOML {:?}: {}", document, e));
+        table["value"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_format_string_styles() {
+        assert_eq!(format_string("plain"), "\"plain\"");
+        assert_eq!(format_string(r"C:\Users\app"), r"'C:\Users\app'");
+        assert_eq!(format_string(r#"say "hi""#), r#"'say "hi"'"#);
+        assert_eq!(format_string(r#"it's "quoted""#), r#""it's \"quoted\"""#);
+        assert_eq!(format_string("tab\there"), "\"tab\\there\"");
+        assert_eq!(format_string("bell\u{7}"), "\"bell\\u0007\"");
+        assert_eq!(
+            format_string("line 1\nline 2"),
+            "\"\"\"\nline 1\nline 2\"\"\""
+        );
+        assert_eq!(format_string("a\\b\nc"), "'''\na\\b\nc'''");
+    }
+
+    #[test]
+    fn test_format_string_multi_line_quotes() {
+        for s in [
+            "\"\"\"\n\"\"\"",
+            "end\n\"",
+            "\n",
+            "\n\nleading",
+            "crlf\r\nline",
+            "''\n\\",
+            "x'\n\\",
+        ] {
+            assert_eq!(reparse(&format_string(s)), s);
+        }
+    }
+
+    #[test]
+    fn test_format_key() {
+        assert_eq!(format_key("db_host-2"), "db_host-2");
+        assert_eq!(format_key("weird.key"), "\"weird.key\"");
+        assert_eq!(format_key("größe"), "\"größe\"");
+        assert_eq!(format_key("sp ace"), "\"sp ace\"");
+        assert_eq!(format_key(""), "\"\"");
+        assert_eq!(format_key_path(&["a", "b.c"]), "a.\"b.c\"");
+    }
+
+    proptest! {
+        #[test]
+        fn test_format_string_round_trips(s in "\\PC*|[\"'\\\\\n\r\t\u{0}-\u{1f}\u{7f} a]*") {
+            prop_assert_eq!(reparse(&format_string(&s)), s);
+        }
+    }
+}
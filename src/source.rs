@@ -0,0 +1,80 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+
+use crate::{Converter, Error};
+
+/// A provider of `(name, value)` variables to convert.
+///
+/// Entries that cannot be loaded are yielded as errors. Errors naming a variable
+/// that does not match the converter's prefix are ignored, so a broken unrelated
+/// variable does not prevent conversion.
+pub trait VarSource {
+    /// Returns the variables of the source in discovery order.
+    fn vars(&self) -> Box<dyn Iterator<Item = Result<(String, String), Error>> + '_>;
+}
+
+/// The environment of the current process.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Env;
+
+impl VarSource for Env {
+    fn vars(&self) -> Box<dyn Iterator<Item = Result<(String, String), Error>> + '_> {
+        Box::new(
+            env::vars_os().map(|(key, value)| match (key.to_str(), value.to_str()) {
+                (Some(key), Some(value)) => Ok((key.to_string(), value.to_string())),
+                _ => Err(Error::NotUnicode {
+                    var: key.to_string_lossy().into_owned(),
+                }),
+            }),
+        )
+    }
+}
+
+impl<K: AsRef<str>, V: AsRef<str>, S> VarSource for HashMap<K, V, S> {
+    fn vars(&self) -> Box<dyn Iterator<Item = Result<(String, String), Error>> + '_> {
+        Box::new(self.iter().map(owned_pair))
+    }
+}
+
+impl<K: AsRef<str>, V: AsRef<str>> VarSource for BTreeMap<K, V> {
+    fn vars(&self) -> Box<dyn Iterator<Item = Result<(String, String), Error>> + '_> {
+        Box::new(self.iter().map(owned_pair))
+    }
+}
+
+impl<K: AsRef<str>, V: AsRef<str>> VarSource for [(K, V)] {
+    fn vars(&self) -> Box<dyn Iterator<Item = Result<(String, String), Error>> + '_> {
+        Box::new(self.iter().map(|(key, value)| owned_pair((key, value))))
+    }
+}
+
+impl<K: AsRef<str>, V: AsRef<str>> VarSource for Vec<(K, V)> {
+    fn vars(&self) -> Box<dyn Iterator<Item = Result<(String, String), Error>> + '_> {
+        self.as_slice().vars()
+    }
+}
+
+/// Reads every variable of `source`, skipping errors about variables that the
+/// converter does not select.
+pub(crate) fn collect<S: VarSource + ?Sized>(
+    converter: &Converter,
+    source: &S,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut vars = Vec::new();
+    for entry in source.vars() {
+        match entry {
+            Ok(entry) => vars.push(entry),
+            Err(err) => match err.var() {
+                Some(var) if converter.match_prefix(var).is_none() => continue,
+                _ => return Err(err),
+            },
+        }
+    }
+    Ok(vars)
+}
+
+fn owned_pair<K: AsRef<str>, V: AsRef<str>>(
+    (key, value): (K, V),
+) -> Result<(String, String), Error> {
+    Ok((key.as_ref().to_string(), value.as_ref().to_string()))
+}
@@ -0,0 +1,547 @@
+use crate::converter::TableStyle;
+use crate::value::Value;
+use crate::{ser, ConflictPolicy, Converter, Error};
+
+/// A value in the configuration tree together with the variable it was read from.
+#[derive(Debug, Clone)]
+pub(crate) struct Leaf {
+    pub(crate) value: Value,
+    /// Name of the environment variable the value was read from.
+    pub(crate) var: String,
+    /// Position of the variable in its source, used for discovery ordering.
+    pub(crate) position: usize,
+    /// Whether the value is a default rather than set by `var`.
+    pub(crate) default: bool,
+    /// The text the value was read from, if any. Deserializing into a string
+    /// gives this text, so `1.10` stays `1.10` rather than becoming `1.1`.
+    pub(crate) raw: Option<String>,
+}
+
+/// A node of the configuration tree: a value, a nested table or an array.
+#[derive(Debug, Clone)]
+pub(crate) enum Node {
+    Leaf(Leaf),
+    Table(Table),
+    Array(Vec<Node>),
+}
+
+/// A TOML table whose entries keep the order in which they were inserted.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Table {
+    entries: Vec<(String, Node)>,
+    /// Whether the table was given whole, like a JSON object, rather than built
+    /// from variable name segments. Its keys are never array indices.
+    literal: bool,
+}
+
+impl Node {
+    /// The discovery position of a leaf, or the earliest position within a table.
+    fn position(&self) -> usize {
+        match self {
+            Node::Leaf(leaf) => leaf.position,
+            Node::Table(table) => table
+                .entries
+                .iter()
+                .map(|(_, node)| node.position())
+                .min()
+                .unwrap_or(usize::MAX),
+            Node::Array(elements) => elements
+                .iter()
+                .map(Node::position)
+                .min()
+                .unwrap_or(usize::MAX),
+        }
+    }
+
+    /// The alphabetically first variable that contributed to the node.
+    pub(crate) fn first_var(&self) -> Option<&str> {
+        match self {
+            Node::Leaf(leaf) => Some(&leaf.var),
+            Node::Table(table) => table.first_var(),
+            Node::Array(elements) => elements.iter().filter_map(Node::first_var).min(),
+        }
+    }
+
+    pub(crate) fn to_toml_value(&self) -> toml::Value {
+        match self {
+            Node::Leaf(leaf) => match &leaf.value {
+                Value::String(value) => toml::Value::String(value.clone()),
+                Value::Integer(value) => toml::Value::Integer(*value),
+                Value::Float(value) => toml::Value::Float(*value),
+                Value::Boolean(value) => toml::Value::Boolean(*value),
+                Value::Datetime(value) => value
+                    .parse()
+                    .map(toml::Value::Datetime)
+                    .unwrap_or_else(|_| toml::Value::String(value.clone())),
+            },
+            Node::Table(table) => toml::Value::Table(table.to_toml_value()),
+            Node::Array(elements) => {
+                toml::Value::Array(elements.iter().map(Node::to_toml_value).collect())
+            }
+        }
+    }
+
+    /// Marks every value within the node as a default.
+    pub(crate) fn mark_default(&mut self) {
+        match self {
+            Node::Leaf(leaf) => leaf.default = true,
+            Node::Table(table) => table
+                .entries
+                .iter_mut()
+                .for_each(|(_, node)| node.mark_default()),
+            Node::Array(elements) => elements.iter_mut().for_each(Node::mark_default),
+        }
+    }
+
+    /// The comment noting where a value came from, if the converter annotates sources.
+    fn source_comment(&self, converter: &Converter) -> String {
+        if !converter.annotate_sources {
+            return String::new();
+        }
+        let is_default = match self {
+            Node::Leaf(leaf) => leaf.default,
+            Node::Array(elements) => elements.iter().all(|element| match element {
+                Node::Leaf(leaf) => leaf.default,
+                _ => false,
+            }),
+            Node::Table(_) => false,
+        };
+        match self.first_var() {
+            _ if is_default => " # default".to_string(),
+            Some(var) => format!(" # from {}", var),
+            None => String::new(),
+        }
+    }
+
+    /// Whether the node is a non-empty array whose elements are all tables.
+    pub(crate) fn is_array_of_tables(&self) -> bool {
+        match self {
+            Node::Array(elements) => {
+                !elements.is_empty()
+                    && elements
+                        .iter()
+                        .all(|element| matches!(element, Node::Table(_)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Formats the node as a TOML value: a scalar, an inline table or an array.
+    pub(crate) fn format_inline(&self, path: &mut Vec<String>, converter: &Converter) -> String {
+        match self {
+            Node::Leaf(leaf) => leaf.value.to_string(),
+            Node::Table(table) => table.format_inline(path, converter),
+            Node::Array(elements) => {
+                let elements: Vec<String> = elements
+                    .iter()
+                    .map(|element| element.format_inline(path, converter))
+                    .collect();
+                format!("[{}]", elements.join(", "))
+            }
+        }
+    }
+}
+
+impl Table {
+    /// The alphabetically first variable that contributed to the table.
+    fn first_var(&self) -> Option<&str> {
+        self.entries
+            .iter()
+            .filter_map(|(_, node)| node.first_var())
+            .min()
+    }
+
+    /// Returns the node at `path`, where array elements are selected by index.
+    pub(crate) fn get(&self, path: &[String]) -> Option<&Node> {
+        let (first, rest) = path.split_first()?;
+        let mut node = &self.entries[self.index_of(first)?].1;
+        for segment in rest {
+            node = match node {
+                Node::Table(table) => &table.entries[table.index_of(segment)?].1,
+                Node::Array(elements) => elements.get(segment.parse::<usize>().ok()?)?,
+                Node::Leaf(_) => return None,
+            };
+        }
+        Some(node)
+    }
+
+    /// Adds the entries of `defaults` whose keys are missing, merging tables present
+    /// in both. Existing values, including whole arrays, are kept.
+    pub(crate) fn fill(&mut self, defaults: Table) {
+        for (key, node) in defaults.entries {
+            match self.index_of(&key) {
+                None => self.entries.push((key, node)),
+                Some(index) => {
+                    if let (Node::Table(table), Node::Table(defaults)) =
+                        (&mut self.entries[index].1, node)
+                    {
+                        table.fill(defaults);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Converts the table into the `toml` crate's data model.
+    pub(crate) fn to_toml_value(&self) -> toml::Table {
+        self.entries
+            .iter()
+            .map(|(key, node)| (key.clone(), node.to_toml_value()))
+            .collect()
+    }
+
+    /// Creates an empty table whose keys are kept as they are by
+    /// [`Table::build_arrays`].
+    pub(crate) fn literal() -> Self {
+        Self {
+            entries: Vec::new(),
+            literal: true,
+        }
+    }
+
+    /// The entries of the table in insertion order.
+    pub(crate) fn entries(&self) -> &[(String, Node)] {
+        &self.entries
+    }
+
+    /// Appends an entry without checking for an existing key.
+    pub(crate) fn push(&mut self, key: String, node: Node) {
+        self.entries.push((key, node));
+    }
+
+    fn index_of(&self, key: &str) -> Option<usize> {
+        self.entries.iter().position(|(k, _)| k == key)
+    }
+
+    /// Inserts the value `node` under `section`.`key`, creating intermediate tables
+    /// as needed.
+    ///
+    /// A key that is also used as a table by another variable, such as `APP_DB`
+    /// alongside `APP_DB__HOST`, is resolved according to `policy`.
+    pub(crate) fn insert(
+        &mut self,
+        section: &[String],
+        key: String,
+        node: Node,
+        policy: &ConflictPolicy,
+    ) -> Result<(), Error> {
+        let Some((first, rest)) = section.split_first() else {
+            return self.insert_value(key, node, policy);
+        };
+        let var = node.first_var().unwrap_or_default().to_string();
+        let index = match self.index_of(first) {
+            Some(index) => index,
+            None => {
+                self.entries
+                    .push((first.clone(), Node::Table(Table::default())));
+                self.entries.len() - 1
+            }
+        };
+
+        let existing = &mut self.entries[index].1;
+        if !matches!(existing, Node::Table(_)) {
+            // An earlier variable set a value where this one needs a table.
+            let mut table = Table::default();
+            match policy {
+                ConflictPolicy::Error => {
+                    return Err(Error::KeyConflict {
+                        var: existing.first_var().unwrap_or_default().to_string(),
+                        table_var: var,
+                    });
+                }
+                ConflictPolicy::TableWins => {}
+                ConflictPolicy::ReservedKey(reserved) => {
+                    let value = std::mem::replace(existing, Node::Table(Table::default()));
+                    table.entries.push((reserved.clone(), value));
+                }
+            }
+            *existing = Node::Table(table);
+        }
+        let Node::Table(table) = existing else {
+            unreachable!("values are replaced by tables above");
+        };
+        table.insert(rest, key, node, policy)
+    }
+
+    fn insert_value(
+        &mut self,
+        key: String,
+        node: Node,
+        policy: &ConflictPolicy,
+    ) -> Result<(), Error> {
+        let Some(index) = self.index_of(&key) else {
+            self.entries.push((key, node));
+            return Ok(());
+        };
+        if let Node::Table(table) = node {
+            // A table parsed from JSON is merged entry by entry with the existing key.
+            let section = [key];
+            for (child_key, child) in table.entries {
+                self.insert(&section, child_key, child, policy)?;
+            }
+            return Ok(());
+        }
+        let var = node.first_var().unwrap_or_default().to_string();
+        match &mut self.entries[index].1 {
+            existing @ (Node::Leaf(_) | Node::Array(_)) => Err(Error::DuplicateKey {
+                var,
+                other: existing.first_var().unwrap_or_default().to_string(),
+            }),
+            Node::Table(table) => match policy {
+                ConflictPolicy::Error => Err(Error::KeyConflict {
+                    var,
+                    table_var: table.first_var().unwrap_or_default().to_string(),
+                }),
+                ConflictPolicy::TableWins => Ok(()),
+                ConflictPolicy::ReservedKey(reserved) => {
+                    table.insert_value(reserved.clone(), node, &ConflictPolicy::Error)
+                }
+            },
+        }
+    }
+
+    /// Converts tables whose keys are all array indices, such as the table built from
+    /// `APP_HOSTS__0` and `APP_HOSTS__1`, into arrays ordered by index.
+    pub(crate) fn build_arrays(&mut self) -> Result<(), Error> {
+        for (_, node) in &mut self.entries {
+            let Node::Table(table) = node else {
+                continue;
+            };
+            table.build_arrays()?;
+            if table.literal {
+                continue;
+            }
+            let Some(indices) = table.array_indices()? else {
+                continue;
+            };
+            let mut elements: Vec<(usize, Node)> = indices
+                .into_iter()
+                .zip(
+                    std::mem::take(&mut table.entries)
+                        .into_iter()
+                        .map(|(_, node)| node),
+                )
+                .collect();
+            elements.sort_by_key(|(index, _)| *index);
+            *node = Node::Array(elements.into_iter().map(|(_, node)| node).collect());
+        }
+        Ok(())
+    }
+
+    /// Returns the index of every entry if the table should become an array, after
+    /// checking that the indices are unique and have no gaps.
+    fn array_indices(&self) -> Result<Option<Vec<usize>>, Error> {
+        let is_index = |key: &str| !key.is_empty() && key.bytes().all(|b| b.is_ascii_digit());
+        let Some((_, indexed)) = self.entries.iter().find(|(key, _)| is_index(key)) else {
+            return Ok(None);
+        };
+        let invalid = |node: &Node, reason: String| Error::InvalidIndex {
+            var: node.first_var().unwrap_or_default().to_string(),
+            reason,
+        };
+        if self.entries.iter().any(|(key, _)| !is_index(key)) {
+            return Err(invalid(
+                indexed,
+                "array indices are mixed with other keys".to_string(),
+            ));
+        }
+        let mut indices = Vec::with_capacity(self.entries.len());
+        for (key, node) in &self.entries {
+            let index = key
+                .parse::<usize>()
+                .map_err(|_| invalid(node, format!("array index {} is too large", key)))?;
+            indices.push(index);
+        }
+        let mut sorted: Vec<(usize, &Node)> = indices
+            .iter()
+            .copied()
+            .zip(self.entries.iter().map(|(_, node)| node))
+            .collect();
+        sorted.sort_by_key(|(index, node)| (*index, node.first_var()));
+        if let Some(&(first, node)) = sorted.first() {
+            if first != 0 {
+                return Err(invalid(
+                    node,
+                    format!("array index {} is used but index 0 is missing", first),
+                ));
+            }
+        }
+        for (expected, window) in sorted.windows(2).enumerate() {
+            let ((previous, previous_node), (index, node)) = (window[0], window[1]);
+            if index == previous {
+                return Err(Error::DuplicateKey {
+                    var: node.first_var().unwrap_or_default().to_string(),
+                    other: previous_node.first_var().unwrap_or_default().to_string(),
+                });
+            }
+            if index != expected + 1 {
+                return Err(invalid(
+                    node,
+                    format!(
+                        "array index {} is used but index {} is missing",
+                        index,
+                        expected + 1
+                    ),
+                ));
+            }
+        }
+        Ok(Some(indices))
+    }
+
+    /// Returns the entries in the order requested by the converter.
+    pub(crate) fn sorted_entries(
+        &self,
+        path: &[String],
+        converter: &Converter,
+    ) -> Vec<&(String, Node)> {
+        let mut entries: Vec<&(String, Node)> = self.entries.iter().collect();
+        converter.key_order.sort(
+            &mut entries,
+            |(key, _)| {
+                let mut entry_path = path.to_vec();
+                entry_path.push(key.clone());
+                entry_path
+            },
+            |(_, node)| node.position(),
+        );
+        entries
+    }
+
+    /// Whether the table has entries that are written below its own `[header]`.
+    pub(crate) fn has_body(&self, depth: usize, converter: &Converter) -> bool {
+        let child_style = converter.table_style_at(depth + 1);
+        self.entries.iter().any(|(_, node)| match node {
+            Node::Leaf(_) => true,
+            Node::Array(_) if node.is_array_of_tables() => child_style != TableStyle::Header,
+            Node::Array(_) => true,
+            Node::Table(_) => child_style != TableStyle::Header,
+        })
+    }
+
+    /// Writes the table at `path` as a TOML document fragment: its own key/value
+    /// pairs first, then its header-style subtables, parents before children.
+    pub(crate) fn write_toml(
+        &self,
+        out: &mut String,
+        path: &mut Vec<String>,
+        converter: &Converter,
+    ) {
+        self.write_body(out, path, converter);
+        self.write_subtables(out, path, converter);
+    }
+
+    /// Writes the key/value pairs of the table, including dotted and inline subtables.
+    fn write_body(&self, out: &mut String, path: &mut Vec<String>, converter: &Converter) {
+        let style = converter.table_style_at(path.len() + 1);
+        for (key, node) in self.sorted_entries(path, converter) {
+            match node {
+                // Arrays of tables are written as `[[header]]` sections instead.
+                Node::Array(_) if node.is_array_of_tables() && style == TableStyle::Header => {}
+                Node::Leaf(_) | Node::Array(_) => {
+                    path.push(key.clone());
+                    let value = node.format_inline(path, converter);
+                    let comment = node.source_comment(converter);
+                    out.push_str(&format!(
+                        "{} = {}{}\n",
+                        ser::format_key(key),
+                        value,
+                        comment
+                    ));
+                    path.pop();
+                }
+                Node::Table(table) => {
+                    path.push(key.clone());
+                    match style {
+                        TableStyle::Header => {}
+                        TableStyle::Dotted => {
+                            table.write_dotted(out, &mut vec![key.clone()], path, converter)
+                        }
+                        TableStyle::Inline => out.push_str(&format!(
+                            "{} = {}\n",
+                            ser::format_key(key),
+                            table.format_inline(path, converter)
+                        )),
+                    }
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    /// Writes the header-style subtables of the table and, recursively, their children.
+    fn write_subtables(&self, out: &mut String, path: &mut Vec<String>, converter: &Converter) {
+        if converter.table_style_at(path.len() + 1) != TableStyle::Header {
+            return;
+        }
+        for (key, node) in self.sorted_entries(path, converter) {
+            path.push(key.clone());
+            match node {
+                Node::Table(table) => {
+                    // Tables that only hold subtables are implied by their children's headers.
+                    if table.has_body(path.len(), converter) || table.entries.is_empty() {
+                        out.push_str(&format!("\n[{}]\n", ser::format_key_path(path)));
+                    }
+                    table.write_toml(out, path, converter);
+                }
+                Node::Array(elements) if node.is_array_of_tables() => {
+                    for element in elements {
+                        let Node::Table(table) = element else {
+                            continue;
+                        };
+                        out.push_str(&format!("\n[[{}]]\n", ser::format_key_path(path)));
+                        table.write_toml(out, path, converter);
+                    }
+                }
+                _ => {}
+            }
+            path.pop();
+        }
+    }
+
+    /// Writes the table as dotted keys, `keys` being the dotted prefix within the parent.
+    fn write_dotted(
+        &self,
+        out: &mut String,
+        keys: &mut Vec<String>,
+        path: &mut Vec<String>,
+        converter: &Converter,
+    ) {
+        if self.entries.is_empty() {
+            out.push_str(&format!("{} = {{}}\n", ser::format_key_path(keys)));
+        }
+        for (key, node) in self.sorted_entries(path, converter) {
+            keys.push(key.clone());
+            path.push(key.clone());
+            match node {
+                Node::Table(table) => table.write_dotted(out, keys, path, converter),
+                _ => {
+                    let value = node.format_inline(path, converter);
+                    let comment = node.source_comment(converter);
+                    out.push_str(&format!(
+                        "{} = {}{}\n",
+                        ser::format_key_path(keys),
+                        value,
+                        comment
+                    ));
+                }
+            }
+            path.pop();
+            keys.pop();
+        }
+    }
+
+    /// Formats the table and all of its subtables as a single inline table.
+    fn format_inline(&self, path: &mut Vec<String>, converter: &Converter) -> String {
+        if self.entries.is_empty() {
+            return "{}".to_string();
+        }
+        let mut pairs = Vec::new();
+        for (key, node) in self.sorted_entries(path, converter) {
+            path.push(key.clone());
+            let value = node.format_inline(path, converter);
+            pairs.push(format!("{} = {}", ser::format_key(key), value));
+            path.pop();
+        }
+        format!("{{ {} }}", pairs.join(", "))
+    }
+}
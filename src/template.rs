@@ -0,0 +1,119 @@
+use crate::converter::ListDelimiter;
+use crate::table::{Leaf, Node};
+use crate::value::{self, Value};
+use crate::Error;
+
+/// A TOML document whose values give the types that variables are coerced to.
+#[derive(Debug)]
+pub(crate) struct Template {
+    root: toml::Table,
+}
+
+impl Template {
+    pub(crate) fn parse(template: &str) -> Result<Self, Error> {
+        let root = template
+            .parse()
+            .map_err(|err: toml::de::Error| Error::InvalidToml {
+                reason: format!("template: {}", err.message()),
+            })?;
+        Ok(Self { root })
+    }
+
+    /// Returns the template value at `path`. Array indices select the element at
+    /// that index, or the first element for indices past the end of the array.
+    pub(crate) fn get(&self, path: &[String]) -> Option<&toml::Value> {
+        let (first, rest) = path.split_first()?;
+        let mut value = self.root.get(first)?;
+        for segment in rest {
+            value = match value {
+                toml::Value::Table(table) => table.get(segment)?,
+                toml::Value::Array(elements) => {
+                    let index: usize = segment.parse().ok()?;
+                    elements.get(index).or(elements.first())?
+                }
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+}
+
+/// Converts `raw` to the type of the template value `expected`. Arrays are split
+/// on `delimiter`, or on commas if the variable has none, and each element is
+/// converted to the type of the template's first element.
+pub(crate) fn coerce(
+    expected: &toml::Value,
+    raw: &str,
+    var: &str,
+    position: usize,
+    delimiter: Option<ListDelimiter>,
+) -> Result<Node, Error> {
+    let mismatch = |expected: &str| Error::TypeMismatch {
+        var: var.to_string(),
+        expected: expected.to_string(),
+    };
+    let leaf = |value, raw: &str| {
+        Node::Leaf(Leaf {
+            value,
+            var: var.to_string(),
+            position,
+            default: false,
+            raw: Some(raw.to_string()),
+        })
+    };
+    match expected {
+        toml::Value::Table(_) => Err(mismatch("table")),
+        toml::Value::Array(elements) => {
+            let element_type = elements.first();
+            if element_type.is_some_and(toml::Value::is_table) {
+                return Err(mismatch("array of tables"));
+            }
+            delimiter
+                .unwrap_or(ListDelimiter::Comma)
+                .split(raw)
+                .iter()
+                .map(|element| match element_type {
+                    Some(element_type) => coerce_scalar(element_type, element)
+                        .map(|value| leaf(value, element))
+                        .ok_or_else(|| mismatch(type_name(element_type))),
+                    None => Ok(leaf(Value::infer(element), element)),
+                })
+                .collect::<Result<_, _>>()
+                .map(Node::Array)
+        }
+        scalar => coerce_scalar(scalar, raw)
+            .map(|value| leaf(value, raw))
+            .ok_or_else(|| mismatch(type_name(scalar))),
+    }
+}
+
+fn coerce_scalar(expected: &toml::Value, raw: &str) -> Option<Value> {
+    match expected {
+        toml::Value::String(_) => Some(Value::String(raw.to_string())),
+        toml::Value::Integer(_) => value::parse_integer(raw).map(Value::Integer),
+        toml::Value::Float(_) => value::parse_float(raw)
+            .or_else(|| value::parse_integer(raw).map(|integer| integer as f64))
+            .map(Value::Float),
+        toml::Value::Boolean(_) => match raw.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Some(Value::Boolean(true)),
+            "false" | "0" | "no" | "off" => Some(Value::Boolean(false)),
+            _ => None,
+        },
+        toml::Value::Datetime(_) => {
+            value::is_datetime(raw).then(|| Value::Datetime(raw.to_string()))
+        }
+        toml::Value::Array(_) | toml::Value::Table(_) => None,
+    }
+}
+
+fn type_name(value: &toml::Value) -> &'static str {
+    match value {
+        toml::Value::String(_) => "string",
+        toml::Value::Integer(_) => "integer",
+        toml::Value::Float(_) => "float",
+        toml::Value::Boolean(_) => "boolean",
+        toml::Value::Datetime(_) => "datetime",
+        toml::Value::Array(_) => "array",
+        toml::Value::Table(_) => "table",
+    }
+}
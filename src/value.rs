@@ -0,0 +1,336 @@
+use std::fmt;
+
+use crate::ser;
+
+/// A typed TOML value inferred from the raw string of an environment variable.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    /// An offset datetime, local datetime, local date or local time, kept verbatim.
+    Datetime(String),
+}
+
+impl Value {
+    /// Infers the most specific TOML scalar type for `raw`, falling back to a string.
+    pub(crate) fn infer(raw: &str) -> Self {
+        if let Some(boolean) = parse_boolean(raw) {
+            Value::Boolean(boolean)
+        } else if let Some(integer) = parse_integer(raw) {
+            Value::Integer(integer)
+        } else if let Some(float) = parse_float(raw) {
+            Value::Float(float)
+        } else if is_datetime(raw) {
+            Value::Datetime(raw.to_string())
+        } else {
+            Value::String(raw.to_string())
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Writes the value as it appears on the right-hand side of a TOML key/value pair.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => f.write_str(&ser::format_string(s)),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => f.write_str(&format_float(*x)),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Datetime(d) => f.write_str(d),
+        }
+    }
+}
+
+fn parse_boolean(raw: &str) -> Option<bool> {
+    match raw {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses a TOML integer: decimal with an optional sign, or `0x`/`0o`/`0b` prefixed.
+pub(crate) fn parse_integer(raw: &str) -> Option<i64> {
+    let (radix, digits) = match raw.get(..2) {
+        Some("0x") => (16, &raw[2..]),
+        Some("0o") => (8, &raw[2..]),
+        Some("0b") => (2, &raw[2..]),
+        _ => (10, raw),
+    };
+    if radix != 10 {
+        if !is_digit_run(digits, |c| c.is_digit(radix)) {
+            return None;
+        }
+        return i64::from_str_radix(&digits.replace('_', ""), radix).ok();
+    }
+
+    let unsigned = digits.strip_prefix(['+', '-']).unwrap_or(digits);
+    if !is_decimal_int(unsigned) {
+        return None;
+    }
+    raw.replace('_', "").parse().ok()
+}
+
+/// Parses a TOML float, including the special `inf` and `nan` values.
+pub(crate) fn parse_float(raw: &str) -> Option<f64> {
+    let unsigned = raw.strip_prefix(['+', '-']).unwrap_or(raw);
+    let negative = raw.starts_with('-');
+    match unsigned {
+        "inf" if negative => return Some(f64::NEG_INFINITY),
+        "inf" => return Some(f64::INFINITY),
+        "nan" if negative => return Some(-f64::NAN),
+        "nan" => return Some(f64::NAN),
+        _ => {}
+    }
+
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (unsigned, None),
+    };
+    let (int_part, fraction) = match mantissa.split_once('.') {
+        Some((int_part, fraction)) => (int_part, Some(fraction)),
+        None => (mantissa, None),
+    };
+    if fraction.is_none() && exponent.is_none() {
+        return None;
+    }
+    if !is_decimal_int(int_part) {
+        return None;
+    }
+    if let Some(fraction) = fraction {
+        if !is_digit_run(fraction, |c| c.is_ascii_digit()) {
+            return None;
+        }
+    }
+    if let Some(exponent) = exponent {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        if !is_digit_run(exponent, |c| c.is_ascii_digit()) {
+            return None;
+        }
+    }
+    // Finite input that overflows is not a float, as out-of-range integers are
+    // not integers.
+    raw.replace('_', "")
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite())
+}
+
+/// Checks for unsigned decimal digits without a leading zero.
+fn is_decimal_int(s: &str) -> bool {
+    is_digit_run(s, |c| c.is_ascii_digit()) && !(s.len() > 1 && s.starts_with('0'))
+}
+
+/// Checks for a non-empty run of digits where each `_` sits between two digits.
+fn is_digit_run(s: &str, is_digit: impl Fn(char) -> bool) -> bool {
+    !s.is_empty()
+        && !s.starts_with('_')
+        && !s.ends_with('_')
+        && !s.contains("__")
+        && s.chars().all(|c| c == '_' || is_digit(c))
+}
+
+/// Checks for an RFC 3339 offset datetime, or a TOML local datetime, date or time.
+pub(crate) fn is_datetime(raw: &str) -> bool {
+    if is_time(raw) {
+        return true;
+    }
+    let Some(date) = raw.get(..10) else {
+        return false;
+    };
+    if !is_date(date) {
+        return false;
+    }
+    let rest = &raw[10..];
+    if rest.is_empty() {
+        return true;
+    }
+    let Some(rest) = rest.strip_prefix(['T', 't', ' ']) else {
+        return false;
+    };
+    let offset_start = rest.find(['Z', 'z', '+', '-']).unwrap_or(rest.len());
+    let (time, offset) = rest.split_at(offset_start);
+    is_time(time) && (offset.is_empty() || is_offset(offset))
+}
+
+/// Checks for a `YYYY-MM-DD` date that exists in the proleptic Gregorian calendar.
+fn is_date(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    if bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return false;
+    }
+    let (Some(year), Some(month), Some(day)) = (
+        parse_fixed(&s[..4]),
+        parse_fixed(&s[5..7]),
+        parse_fixed(&s[8..]),
+    ) else {
+        return false;
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days_in_month).contains(&day)
+}
+
+/// Checks for a `HH:MM:SS` time with optional fractional seconds.
+fn is_time(s: &str) -> bool {
+    let (hms, fraction) = match s.split_once('.') {
+        Some((hms, fraction)) => (hms, Some(fraction)),
+        None => (s, None),
+    };
+    let bytes = hms.as_bytes();
+    if bytes.len() != 8 || bytes[2] != b':' || bytes[5] != b':' {
+        return false;
+    }
+    let (Some(hour), Some(minute), Some(second)) = (
+        parse_fixed(&hms[..2]),
+        parse_fixed(&hms[3..5]),
+        parse_fixed(&hms[6..]),
+    ) else {
+        return false;
+    };
+    hour < 24
+        && minute < 60
+        && second <= 60
+        && fraction.is_none_or(|f| !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Checks for a `Z` or `+HH:MM` / `-HH:MM` UTC offset.
+fn is_offset(s: &str) -> bool {
+    if s == "Z" || s == "z" {
+        return true;
+    }
+    let bytes = s.as_bytes();
+    if bytes.len() != 6 || !matches!(bytes[0], b'+' | b'-') || bytes[3] != b':' {
+        return false;
+    }
+    matches!(
+        (parse_fixed(&s[1..3]), parse_fixed(&s[4..])),
+        (Some(hour), Some(minute)) if hour < 24 && minute < 60
+    )
+}
+
+/// Parses a fixed-width run of ASCII digits.
+fn parse_fixed(s: &str) -> Option<u32> {
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        s.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Formats a float so that it always reads back as a TOML float rather than an integer.
+fn format_float(x: f64) -> String {
+    if x.is_nan() {
+        if x.is_sign_negative() { "-nan" } else { "nan" }.to_string()
+    } else if x.is_infinite() {
+        if x.is_sign_negative() { "-inf" } else { "inf" }.to_string()
+    } else {
+        format!("{:?}", x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_integers() {
+        assert_eq!(Value::infer("8080"), Value::Integer(8080));
+        assert_eq!(Value::infer("-17"), Value::Integer(-17));
+        assert_eq!(Value::infer("+0"), Value::Integer(0));
+        assert_eq!(Value::infer("1_000_000"), Value::Integer(1_000_000));
+        assert_eq!(Value::infer("0xDEAD_beef"), Value::Integer(0xDEAD_BEEF));
+        assert_eq!(Value::infer("0o755"), Value::Integer(0o755));
+        assert_eq!(Value::infer("0b1101"), Value::Integer(0b1101));
+        for raw in [
+            "0123",
+            "1__0",
+            "_1",
+            "1_",
+            "-0x1",
+            "0x",
+            "0xG",
+            "99999999999999999999",
+        ] {
+            assert_eq!(Value::infer(raw), Value::String(raw.to_string()), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_infer_floats() {
+        assert_eq!(Value::infer("2.5"), Value::Float(2.5));
+        assert_eq!(Value::infer("-0.01"), Value::Float(-0.01));
+        assert_eq!(Value::infer("5e+22"), Value::Float(5e22));
+        assert_eq!(Value::infer("6.626E-34"), Value::Float(6.626e-34));
+        assert_eq!(
+            Value::infer("224_617.445_991"),
+            Value::Float(224_617.445_991)
+        );
+        assert_eq!(Value::infer("-inf"), Value::Float(f64::NEG_INFINITY));
+        assert!(matches!(Value::infer("nan"), Value::Float(x) if x.is_nan()));
+        for raw in [
+            "1.", ".5", "1.2.3", "01.5", "1e", "Infinity", "NaN", "1e400", "-1.5e309",
+        ] {
+            assert_eq!(Value::infer(raw), Value::String(raw.to_string()), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_infer_booleans() {
+        assert_eq!(Value::infer("true"), Value::Boolean(true));
+        assert_eq!(Value::infer("false"), Value::Boolean(false));
+        assert_eq!(Value::infer("True"), Value::String("True".to_string()));
+        assert_eq!(Value::infer("yes"), Value::String("yes".to_string()));
+    }
+
+    #[test]
+    fn test_infer_datetimes() {
+        for raw in [
+            "1979-05-27T07:32:00Z",
+            "1979-05-27T00:32:00.999999-07:00",
+            "1979-05-27 07:32:00z",
+            "1979-05-27T07:32:00",
+            "2024-02-29",
+            "07:32:00",
+            "00:32:00.5",
+        ] {
+            assert_eq!(
+                Value::infer(raw),
+                Value::Datetime(raw.to_string()),
+                "{}",
+                raw
+            );
+        }
+        for raw in [
+            "2023-02-29",
+            "1979-13-01",
+            "1979-05-27T25:00:00",
+            "07:32",
+            "1979-05-27T07:32:00+7",
+        ] {
+            assert_eq!(Value::infer(raw), Value::String(raw.to_string()), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_display_native_types() {
+        assert_eq!(Value::Integer(0o755).to_string(), "493");
+        assert_eq!(Value::Float(1.0).to_string(), "1.0");
+        assert_eq!(Value::Float(f64::NAN).to_string(), "nan");
+        assert_eq!(Value::Float(f64::NEG_INFINITY).to_string(), "-inf");
+        assert_eq!(Value::Boolean(true).to_string(), "true");
+        assert_eq!(
+            Value::Datetime("07:32:00".to_string()).to_string(),
+            "07:32:00"
+        );
+        assert_eq!(Value::String("8080".to_string()).to_string(), "\"8080\"");
+    }
+}